@@ -0,0 +1,26 @@
+#!/bin/sh
+# Type-checks the crate for every target layout.rs names, so that its compile-time layout assertions, including the
+# Apple-only CFRuntimeBase and CFConstantString ones, run for each of them. Nothing is linked, so no Apple SDK is
+# needed. Tier 2 targets use the installed standard library; tier 3 ones build it with nightly, which needs rust-src.
+
+set -eu
+
+cd "$(dirname "$0")/.."
+
+TARGETS="x86_64-apple-darwin aarch64-apple-darwin aarch64-apple-ios x86_64-unknown-linux-gnu aarch64-unknown-linux-gnu i686-unknown-linux-gnu"
+TIER3_TARGETS="arm64_32-apple-watchos armv7k-apple-watchos i386-apple-ios"
+
+rustup target add $TARGETS
+rustup component add rust-src --toolchain nightly
+
+for target in $TARGETS; do
+  echo "checking $target"
+  cargo check --lib --target "$target"
+  cargo check --lib --target "$target" --all-features
+done
+
+for target in $TIER3_TARGETS; do
+  echo "checking $target"
+  cargo +nightly check -Zbuild-std=std --lib --target "$target"
+  cargo +nightly check -Zbuild-std=std --lib --target "$target" --all-features
+done
@@ -1,40 +1,45 @@
 use crate::*;
 
-pub type CFAllocatorRetainCallBack = unsafe extern fn(info: *const c_void) -> *const c_void;
-pub type CFAllocatorReleaseCallBack = unsafe extern fn(info: *const c_void);
-pub type CFAllocatorCopyDescriptionCallBack = unsafe extern fn(info: *const c_void) -> CFStringRef;
-pub type CFAllocatorAllocateCallBack = unsafe extern fn(allocSize: CFIndex, hint: CFOptionFlags, info: *const c_void) -> *mut c_void;
-pub type CFAllocatorReallocateCallBack = unsafe extern fn(ptr: *mut c_void, newsize: CFIndex, hint: CFOptionFlags, info: *mut c_void) -> *mut c_void;
-pub type CFAllocatorDeallocateCallBack = unsafe extern fn(ptr: *mut c_void, info: *const c_void);
-pub type CFAllocatorPreferredSizeCallBack = unsafe extern fn(size: CFIndex, hint: CFOptionFlags, info: *const c_void) -> CFIndex;
-
-#[repr(C)] pub struct CFAllocatorContext {
-  version: CFIndex,
-  info: *mut c_void,
-  retain: CFAllocatorRetainCallBack,
-  release: CFAllocatorReleaseCallBack,
-  copyDescription: CFAllocatorCopyDescriptionCallBack,
-  allocate: CFAllocatorAllocateCallBack,
-  reallocate: CFAllocatorReallocateCallBack,
-  deallocate: CFAllocatorDeallocateCallBack,
-  preferredSize: CFAllocatorPreferredSizeCallBack
-}
-
-mod ext {
+use std::alloc::{GlobalAlloc, Layout};
+use std::any;
+use std::sync::Arc;
+
+pub type CFAllocatorRetainCallBack = unsafe extern "C" fn(info: *const c_void) -> *const c_void;
+pub type CFAllocatorReleaseCallBack = unsafe extern "C" fn(info: *const c_void);
+pub type CFAllocatorCopyDescriptionCallBack = unsafe extern "C" fn(info: *const c_void) -> Option<CFStringRef>;
+pub type CFAllocatorAllocateCallBack = unsafe extern "C" fn(allocSize: CFIndex, hint: CFOptionFlags, info: *const c_void) -> *mut c_void;
+pub type CFAllocatorReallocateCallBack = unsafe extern "C" fn(ptr: *mut c_void, newsize: CFIndex, hint: CFOptionFlags, info: *mut c_void) -> *mut c_void;
+pub type CFAllocatorDeallocateCallBack = unsafe extern "C" fn(ptr: *mut c_void, info: *const c_void);
+pub type CFAllocatorPreferredSizeCallBack = unsafe extern "C" fn(size: CFIndex, hint: CFOptionFlags, info: *const c_void) -> CFIndex;
+
+// Mirrors CFAllocatorContext in CFBase.h. Any callback may be NULL, as in contexts CoreFoundation hands back.
+#[repr(C)] #[derive(Debug, Clone, Copy)] pub struct CFAllocatorContext {
+  pub version: CFIndex,
+  pub info: *mut c_void,
+  pub retain: Option<CFAllocatorRetainCallBack>,
+  pub release: Option<CFAllocatorReleaseCallBack>,
+  pub copyDescription: Option<CFAllocatorCopyDescriptionCallBack>,
+  pub allocate: Option<CFAllocatorAllocateCallBack>,
+  pub reallocate: Option<CFAllocatorReallocateCallBack>,
+  pub deallocate: Option<CFAllocatorDeallocateCallBack>,
+  pub preferredSize: Option<CFAllocatorPreferredSizeCallBack>
+}
+
+#[cfg(target_vendor = "apple")]
+pub(crate) mod ext {
   use crate::*;
 
-  extern {
+  cf_extern! {
     pub fn CFAllocatorGetTypeID() -> CFTypeID;
     pub fn CFAllocatorSetDefault(allocator: CFAllocatorRef);
     pub fn CFAllocatorGetDefault() -> CFAllocatorRef;
-    pub fn CFAllocatorCreate(allocator: CFAllocatorRef, context: *mut CFAllocatorContext) -> CFAllocatorRef;
-    pub fn CFAllocatorAllocate(allocator: CFAllocatorRef, size: CFIndex, hint: CFOptionFlags) -> *mut c_void;
-    pub fn CFAllocatorReallocate(allocator: CFAllocatorRef, ptr: *mut c_void, newsize: CFIndex, hint: CFOptionFlags) -> *mut c_void;
-    pub fn CFAllocatorDeallocate(allocator: CFAllocatorRef, ptr: *mut c_void);
-    pub fn CFAllocatorGetPreferredSizeForSize(allocator: CFAllocatorRef, size: CFIndex, hint: CFOptionFlags) -> CFIndex;
-    pub fn CFAllocatorGetContext(allocator: CFAllocatorRef, context: *mut CFAllocatorContext);
-
-    pub static kCFAllocatorDefault: CFAllocatorRef;
+    pub fn CFAllocatorCreate(allocator: Option<CFAllocatorRef>, context: *mut CFAllocatorContext) -> Option<CFAllocatorRef>;
+    pub fn CFAllocatorAllocate(allocator: Option<CFAllocatorRef>, size: CFIndex, hint: CFOptionFlags) -> *mut c_void;
+    pub fn CFAllocatorReallocate(allocator: Option<CFAllocatorRef>, ptr: *mut c_void, newsize: CFIndex, hint: CFOptionFlags) -> *mut c_void;
+    pub fn CFAllocatorDeallocate(allocator: Option<CFAllocatorRef>, ptr: *mut c_void);
+    pub fn CFAllocatorGetPreferredSizeForSize(allocator: Option<CFAllocatorRef>, size: CFIndex, hint: CFOptionFlags) -> CFIndex;
+    pub fn CFAllocatorGetContext(allocator: Option<CFAllocatorRef>, context: *mut CFAllocatorContext);
+
     pub static kCFAllocatorSystemDefault: CFAllocatorRef;
     pub static kCFAllocatorMalloc: CFAllocatorRef;
     pub static kCFAllocatorMallocZone: CFAllocatorRef;
@@ -43,59 +48,590 @@ mod ext {
   }
 }
 
-#[repr(transparent)] pub struct CFAllocatorRef(usize);
+#[cfg(not(target_vendor = "apple"))]
+pub(crate) use crate::portable::allocator as ext;
+
+#[cfg(not(feature = "backend"))]
+use self::ext as ffi;
+
+#[cfg(feature = "backend")]
+use crate::backend::allocator as ffi;
+
+cf_class! {
+  pub unsafe class CFAllocatorRef: CFTypeRef = CFAllocatorGetTypeID where Send, Sync;
+}
+
+pub fn CFAllocatorGetTypeID() -> CFTypeID {
+  return unsafe { ffi::CFAllocatorGetTypeID() };
+}
+
+pub unsafe fn CFAllocatorSetDefault<T: Subtype<CFAllocatorRef>>(allocator: &T) {
+  return ffi::CFAllocatorSetDefault(raw(allocator));
+}
+
+// Retained, as with_default_allocator lets CoreFoundation release a default once it has been replaced.
+pub fn CFAllocatorGetDefault() -> CFOwned<CFAllocatorRef> {
+  return CFRetain(&unsafe { ffi::CFAllocatorGetDefault() });
+}
+
+pub unsafe fn CFAllocatorCreate(allocator: Option<&CFAllocatorRef>, context: *mut CFAllocatorContext) -> Option<CFOwned<CFAllocatorRef>> {
+  return ffi::CFAllocatorCreate(allocator.map(raw), context).map(|allocator| CFOwned::from_create_rule(allocator));
+}
+
+pub unsafe fn CFAllocatorAllocate(allocator: Option<&CFAllocatorRef>, size: CFIndex, hint: CFOptionFlags) -> *mut c_void {
+  return ffi::CFAllocatorAllocate(allocator.map(raw), size, hint);
+}
+
+pub unsafe fn CFAllocatorReallocate(allocator: Option<&CFAllocatorRef>, ptr: *mut c_void, newsize: CFIndex, hint: CFOptionFlags) -> *mut c_void {
+  return ffi::CFAllocatorReallocate(allocator.map(raw), ptr, newsize, hint);
+}
+
+pub unsafe fn CFAllocatorDeallocate(allocator: Option<&CFAllocatorRef>, ptr: *mut c_void) {
+  return ffi::CFAllocatorDeallocate(allocator.map(raw), ptr);
+}
+
+pub fn CFAllocatorGetPreferredSizeForSize(allocator: Option<&CFAllocatorRef>, size: CFIndex, hint: CFOptionFlags) -> CFIndex {
+  return unsafe { ffi::CFAllocatorGetPreferredSizeForSize(allocator.map(raw), size, hint) };
+}
+
+pub unsafe fn CFAllocatorGetContext(allocator: Option<&CFAllocatorRef>, context: *mut CFAllocatorContext) {
+  return ffi::CFAllocatorGetContext(allocator.map(raw), context);
+}
+
+// NULL in C: functions taking an optional allocator use the current default for None.
+pub static kCFAllocatorDefault: Option<&'static CFAllocatorRef> = None;
+pub static kCFAllocatorSystemDefault: CFConstant<CFAllocatorRef> = cf_constant!(ext::kCFAllocatorSystemDefault);
+pub static kCFAllocatorMalloc: CFConstant<CFAllocatorRef> = cf_constant!(ext::kCFAllocatorMalloc);
+pub static kCFAllocatorMallocZone: CFConstant<CFAllocatorRef> = cf_constant!(ext::kCFAllocatorMallocZone);
+pub static kCFAllocatorNull: CFConstant<CFAllocatorRef> = cf_constant!(ext::kCFAllocatorNull);
+pub static kCFAllocatorUseContext: CFConstant<CFAllocatorRef> = cf_constant!(ext::kCFAllocatorUseContext);
+
+// Setting a default leaves CoreFoundation with an extra reference to the new allocator and, once it is replaced in
+// turn, to the previous one. Both are given back once the previous default has been restored.
+struct RestoreDefault {
+  previous: CFOwned<CFAllocatorRef>,
+  installed: CFOwned<CFAllocatorRef>
+}
+
+impl Drop for RestoreDefault {
+  fn drop(&mut self) {
+    unsafe {
+      CFAllocatorSetDefault(&*self.previous);
 
-unsafe impl Subtype<CFTypeRef> for CFAllocatorRef {
-  unsafe fn upcast(&self) -> CFTypeRef {
-    return CFTypeRef(self.0);
+      if self.previous.0 != self.installed.0 {
+        CFRelease(CFOwned::from_create_rule(ptr::read(&*self.installed)));
+        CFRelease(CFOwned::from_create_rule(ptr::read(&*self.previous)));
+      }
+    }
   }
 }
 
-unsafe impl Subtype<CFAllocatorRef> for CFAllocatorRef {
-  unsafe fn upcast(&self) -> CFAllocatorRef {
-    return CFAllocatorRef(self.0);
+// Runs `body` with `allocator` as this thread's default, then puts the previous default back. Objects made in `body`
+// keep `allocator` alive for as long as they need it.
+pub fn with_default_allocator<T: Subtype<CFAllocatorRef>, R>(allocator: &T, body: impl FnOnce() -> R) -> R {
+  let _restore = RestoreDefault { previous: CFAllocatorGetDefault(), installed: CFRetain(allocator.upcast()) };
+
+  unsafe { CFAllocatorSetDefault(allocator) };
+
+  return body();
+}
+
+// Implemented by Rust types that manage the memory of a CF allocator, which CFAllocatorBuilder makes from them. The
+// pointers passed back are ones the same value handed out. Unsafe, as CoreFoundation trusts what it is given:
+// `allocate` and `reallocate` return null or a block of at least `size` bytes, aligned for any CF object, that stays
+// valid until it is reallocated or deallocated.
+pub unsafe trait CFAllocatorCallbacks: Send + Sync + 'static {
+  fn allocate(&self, size: CFIndex, hint: CFOptionFlags) -> *mut c_void;
+
+  // Returning null reports failure and leaves `ptr` as it was, which is all CoreFoundation does without the callback.
+  fn reallocate(&self, _ptr: *mut c_void, _size: CFIndex, _hint: CFOptionFlags) -> *mut c_void {
+    return ptr::null_mut();
+  }
+
+  fn deallocate(&self, ptr: *mut c_void);
+
+  fn preferred_size(&self, size: CFIndex, _hint: CFOptionFlags) -> CFIndex {
+    return size;
+  }
+
+  fn description(&self) -> String {
+    return format!("<CFAllocatorCallbacks {}>", any::type_name::<Self>());
   }
 }
 
-pub fn CFAllocatorGetTypeID() -> CFTypeID {
-  return unsafe { ext::CFAllocatorGetTypeID() };
+unsafe extern "C" fn callbacks_retain<C: CFAllocatorCallbacks>(info: *const c_void) -> *const c_void {
+  Arc::increment_strong_count(info as *const C);
+
+  return info;
+}
+
+unsafe extern "C" fn callbacks_release<C: CFAllocatorCallbacks>(info: *const c_void) {
+  Arc::decrement_strong_count(info as *const C);
+}
+
+unsafe extern "C" fn callbacks_copy_description<C: CFAllocatorCallbacks>(info: *const c_void) -> Option<CFStringRef> {
+  let description = (*(info as *const C)).description();
+
+  return CFStringCreateWithBytes(Some(&kCFAllocatorSystemDefault), description.as_bytes(), CFStringEncoding::kCFStringEncodingUTF8, false).map(CFOwned::into_raw);
+}
+
+unsafe extern "C" fn callbacks_allocate<C: CFAllocatorCallbacks>(allocSize: CFIndex, hint: CFOptionFlags, info: *const c_void) -> *mut c_void {
+  return (*(info as *const C)).allocate(allocSize, hint);
+}
+
+unsafe extern "C" fn callbacks_reallocate<C: CFAllocatorCallbacks>(ptr: *mut c_void, newsize: CFIndex, hint: CFOptionFlags, info: *mut c_void) -> *mut c_void {
+  return (*(info as *const C)).reallocate(ptr, newsize, hint);
+}
+
+unsafe extern "C" fn callbacks_deallocate<C: CFAllocatorCallbacks>(ptr: *mut c_void, info: *const c_void) {
+  (*(info as *const C)).deallocate(ptr);
+}
+
+unsafe extern "C" fn callbacks_preferred_size<C: CFAllocatorCallbacks>(size: CFIndex, hint: CFOptionFlags, info: *const c_void) -> CFIndex {
+  return (*(info as *const C)).preferred_size(size, hint);
+}
+
+// Makes an allocator out of a CFAllocatorCallbacks value, which it owns and drops once the allocator and everything
+// made with it have been released. Panics in the callbacks abort the process, as they cannot unwind through
+// CoreFoundation.
+pub struct CFAllocatorBuilder<'a, C> {
+  callbacks: Arc<C>,
+  allocator: Option<&'a CFAllocatorRef>
+}
+
+impl<'a, C: CFAllocatorCallbacks> CFAllocatorBuilder<'a, C> {
+  pub fn new(callbacks: C) -> CFAllocatorBuilder<'a, C> {
+    return CFAllocatorBuilder { callbacks: Arc::new(callbacks), allocator: None };
+  }
+
+  // Where the allocator itself comes from, the current default unless set. kCFAllocatorUseContext takes it from the
+  // callbacks.
+  pub fn allocator(mut self, allocator: &'a CFAllocatorRef) -> CFAllocatorBuilder<'a, C> {
+    self.allocator = Some(allocator);
+
+    return self;
+  }
+
+  // A context calling into the builder's value, which stays alive as long as the builder or an allocator made from it.
+  // Crate-private, as nothing ties the context to the builder it points into.
+  pub(crate) fn context(&self) -> CFAllocatorContext {
+    return CFAllocatorContext {
+      version: 0,
+      info: Arc::as_ptr(&self.callbacks) as *mut c_void,
+      retain: Some(callbacks_retain::<C>),
+      release: Some(callbacks_release::<C>),
+      copyDescription: Some(callbacks_copy_description::<C>),
+      allocate: Some(callbacks_allocate::<C>),
+      reallocate: Some(callbacks_reallocate::<C>),
+      deallocate: Some(callbacks_deallocate::<C>),
+      preferredSize: Some(callbacks_preferred_size::<C>)
+    };
+  }
+
+  pub fn create(self) -> Option<CFOwned<CFAllocatorRef>> {
+    let mut context = self.context();
+
+    return unsafe { CFAllocatorCreate(self.allocator, &mut context) };
+  }
+}
+
+// CoreFoundation passes no size when freeing, so blocks from a Rust allocator are prefixed with theirs, padded to keep
+// this alignment.
+pub(crate) const kCFAllocatorBlockAlignment: usize = 16;
+
+struct GlobalAllocCallbacks<A>(A);
+
+impl<A> GlobalAllocCallbacks<A> {
+  fn layout(size: CFIndex) -> Option<Layout> {
+    return Layout::from_size_align((size as usize).checked_add(kCFAllocatorBlockAlignment)?, kCFAllocatorBlockAlignment).ok();
+  }
+
+  unsafe fn block(ptr: *mut c_void) -> (*mut u8, Layout) {
+    let block = (ptr as *mut u8).sub(kCFAllocatorBlockAlignment);
+
+    return (block, Layout::from_size_align_unchecked(*(block as *const usize), kCFAllocatorBlockAlignment));
+  }
+
+  unsafe fn place(block: *mut u8, layout: Layout) -> *mut c_void {
+    if block.is_null() {
+      return ptr::null_mut();
+    }
+
+    *(block as *mut usize) = layout.size();
+
+    return block.add(kCFAllocatorBlockAlignment) as *mut c_void;
+  }
+}
+
+unsafe impl<A: GlobalAlloc + Send + Sync + 'static> CFAllocatorCallbacks for GlobalAllocCallbacks<A> {
+  fn allocate(&self, size: CFIndex, _hint: CFOptionFlags) -> *mut c_void {
+    return match GlobalAllocCallbacks::<A>::layout(size) {
+      Some(layout) => unsafe { GlobalAllocCallbacks::<A>::place(self.0.alloc(layout), layout) },
+      None => ptr::null_mut()
+    };
+  }
+
+  fn reallocate(&self, ptr: *mut c_void, size: CFIndex, _hint: CFOptionFlags) -> *mut c_void {
+    return match GlobalAllocCallbacks::<A>::layout(size) {
+      Some(new_layout) => unsafe {
+        let (block, layout) = GlobalAllocCallbacks::<A>::block(ptr);
+
+        GlobalAllocCallbacks::<A>::place(self.0.realloc(block, layout, new_layout.size()), new_layout)
+      },
+      None => ptr::null_mut()
+    };
+  }
+
+  fn deallocate(&self, ptr: *mut c_void) {
+    unsafe {
+      let (block, layout) = GlobalAllocCallbacks::<A>::block(ptr);
+
+      self.0.dealloc(block, layout);
+    }
+  }
+
+  // Sizes that cannot be rounded up, negative or close to CFIndex::MAX, come back unchanged.
+  fn preferred_size(&self, size: CFIndex, _hint: CFOptionFlags) -> CFIndex {
+    let alignment = kCFAllocatorBlockAlignment as CFIndex;
+
+    if size < 0 {
+      return size;
+    }
+
+    return size.checked_add(alignment - 1).map_or(size, |size| size / alignment * alignment);
+  }
+
+  fn description(&self) -> String {
+    return format!("<GlobalAlloc {}>", any::type_name::<A>());
+  }
 }
 
-pub unsafe fn CFAllocatorSetDefault<T: Subtype<CFAllocatorRef>>(allocator: T) {
-  return ext::CFAllocatorSetDefault(allocator.upcast());
+// Creates an allocator that hands out memory from `alloc`, which it owns and drops once the allocator and everything
+// made with it have been released. The allocator itself comes from `allocator`.
+pub fn CFAllocatorCreateWithGlobalAlloc<A: GlobalAlloc + Send + Sync + 'static>(allocator: Option<&CFAllocatorRef>, alloc: A) -> Option<CFOwned<CFAllocatorRef>> {
+  let builder = CFAllocatorBuilder::new(GlobalAllocCallbacks(alloc));
+
+  return match allocator {
+    Some(allocator) => builder.allocator(allocator).create(),
+    None => builder.create()
+  };
 }
 
-pub fn CFAllocatorGetDefault() -> CFAllocatorRef {
-  return unsafe { ext::CFAllocatorGetDefault() };
+// Hands out Rust memory from a CF allocator, so that it comes from the same zone as the CF objects it sits next to.
+// CoreFoundation only aligns blocks for its own types, so every block is padded to the alignment asked for and
+// preceded by the address CoreFoundation returned.
+pub struct CFAllocatorAlloc {
+  allocator: CFOwned<CFAllocatorRef>
 }
 
-pub unsafe fn CFAllocatorCreate<T: Subtype<CFAllocatorRef>>(allocator: T, context: *mut CFAllocatorContext) -> CFAllocatorRef {
-  return ext::CFAllocatorCreate(allocator.upcast(), context);
+impl CFAllocatorAlloc {
+  pub fn new(allocator: &CFAllocatorRef) -> CFAllocatorAlloc {
+    return CFAllocatorAlloc { allocator: CFRetain(allocator) };
+  }
+
+  pub fn allocator(&self) -> &CFAllocatorRef {
+    return &self.allocator;
+  }
+
+  fn padded_size(layout: Layout) -> Option<CFIndex> {
+    return CFIndex::try_from(layout.size().checked_add(layout.align() - 1 + mem::size_of::<usize>())?).ok();
+  }
+
+  // Offsets a block from CoreFoundation past its header, then to `align`.
+  unsafe fn offset(block: *mut u8, align: usize) -> usize {
+    let header = mem::size_of::<usize>();
+
+    return header + block.add(header).align_offset(align);
+  }
+
+  unsafe fn block(ptr: *mut u8) -> *mut u8 {
+    return ptr::read_unaligned((ptr as *const *mut u8).sub(1));
+  }
+
+  unsafe fn place(block: *mut u8, offset: usize) -> *mut u8 {
+    let ptr = block.add(offset);
+
+    ptr::write_unaligned((ptr as *mut *mut u8).sub(1), block);
+
+    return ptr;
+  }
 }
 
-pub unsafe fn CFAllocatorAllocate<T: Subtype<CFAllocatorRef>>(allocator: T, size: CFIndex, hint: CFOptionFlags) -> *mut c_void {
-  return ext::CFAllocatorAllocate(allocator.upcast(), size, hint);
+// Takes the current default once, and keeps it after the default changes, so that every block goes back to the
+// allocator it came from whichever thread or default frees it.
+impl Default for CFAllocatorAlloc {
+  fn default() -> CFAllocatorAlloc {
+    return CFAllocatorAlloc { allocator: CFAllocatorGetDefault() };
+  }
 }
 
-pub unsafe fn CFAllocatorReallocate<T: Subtype<CFAllocatorRef>>(allocator: T, ptr: *mut c_void, newsize: CFIndex, hint: CFOptionFlags) -> *mut c_void {
-  return ext::CFAllocatorReallocate(allocator.upcast(), ptr, newsize, hint);
+impl Clone for CFAllocatorAlloc {
+  fn clone(&self) -> CFAllocatorAlloc {
+    return CFAllocatorAlloc { allocator: self.allocator.clone() };
+  }
 }
 
-pub unsafe fn CFAllocatorDeallocate<T: Subtype<CFAllocatorRef>>(allocator: T, ptr: *mut c_void) {
-  return ext::CFAllocatorDeallocate(allocator.upcast(), ptr);
+impl fmt::Debug for CFAllocatorAlloc {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return f.debug_tuple("CFAllocatorAlloc").field(&self.allocator()).finish();
+  }
 }
 
-pub fn CFAllocatorGetPreferredSizeForSize<T: Subtype<CFAllocatorRef>>(allocator: T, size: CFIndex, hint: CFOptionFlags) -> CFIndex {
-  return unsafe { ext::CFAllocatorGetPreferredSizeForSize(allocator.upcast(), size, hint) };
+unsafe impl GlobalAlloc for CFAllocatorAlloc {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    let size = match CFAllocatorAlloc::padded_size(layout) {
+      Some(size) => size,
+      None => return ptr::null_mut()
+    };
+    let block = CFAllocatorAllocate(Some(self.allocator()), size, CFOptionFlags(0)) as *mut u8;
+
+    if block.is_null() {
+      return ptr::null_mut();
+    }
+
+    return CFAllocatorAlloc::place(block, CFAllocatorAlloc::offset(block, layout.align()));
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+    CFAllocatorDeallocate(Some(self.allocator()), CFAllocatorAlloc::block(ptr) as *mut c_void);
+  }
+
+  // The block may move to an address with a different offset to the alignment, taking the contents with it.
+  unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+    let size = match CFAllocatorAlloc::padded_size(Layout::from_size_align_unchecked(new_size, layout.align())) {
+      Some(size) => size,
+      None => return ptr::null_mut()
+    };
+    let block = CFAllocatorAlloc::block(ptr);
+    let offset = ptr.offset_from(block) as usize;
+    let block = CFAllocatorReallocate(Some(self.allocator()), block as *mut c_void, size, CFOptionFlags(0)) as *mut u8;
+
+    if block.is_null() {
+      return ptr::null_mut();
+    }
+
+    let new_offset = CFAllocatorAlloc::offset(block, layout.align());
+
+    if new_offset != offset {
+      ptr::copy(block.add(offset), block.add(new_offset), layout.size().min(new_size));
+    }
+
+    return CFAllocatorAlloc::place(block, new_offset);
+  }
 }
 
-pub unsafe fn CFAllocatorGetContext<T: Subtype<CFAllocatorRef>>(allocator: T, context: *mut CFAllocatorContext) {
-  return ext::CFAllocatorGetContext(allocator.upcast(), context);
+#[cfg(feature = "allocator-api2")]
+unsafe impl allocator_api2::alloc::Allocator for CFAllocatorAlloc {
+  fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, allocator_api2::alloc::AllocError> {
+    if layout.size() == 0 {
+      let dangling = unsafe { NonNull::new_unchecked(layout.align() as *mut u8) };
+
+      return Ok(NonNull::slice_from_raw_parts(dangling, 0));
+    }
+
+    return match NonNull::new(unsafe { self.alloc(layout) }) {
+      Some(ptr) => Ok(NonNull::slice_from_raw_parts(ptr, layout.size())),
+      None => Err(allocator_api2::alloc::AllocError)
+    };
+  }
+
+  unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+    if layout.size() != 0 {
+      self.dealloc(ptr.as_ptr(), layout);
+    }
+  }
 }
 
-pub static kCFAllocatorDefault: &'static CFAllocatorRef = unsafe { &ext::kCFAllocatorDefault };
-pub static kCFAllocatorSystemDefault: &'static CFAllocatorRef = unsafe { &ext::kCFAllocatorSystemDefault };
-pub static kCFAllocatorMalloc: &'static CFAllocatorRef = unsafe { &ext::kCFAllocatorMalloc };
-pub static kCFAllocatorMallocZone: &'static CFAllocatorRef = unsafe { &ext::kCFAllocatorMallocZone };
-pub static kCFAllocatorNull: &'static CFAllocatorRef = unsafe { &ext::kCFAllocatorNull };
-pub static kCFAllocatorUseContext: &'static CFAllocatorRef = unsafe { &ext::kCFAllocatorUseContext };
+#[cfg(test)]
+mod tests {
+  use crate::*;
+
+  use std::alloc::{GlobalAlloc, Layout, System};
+  use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+  use std::sync::Arc;
+
+  #[derive(Default)]
+  struct Counter {
+    allocations: AtomicUsize,
+    deallocations: AtomicUsize,
+    dropped: AtomicBool
+  }
+
+  struct Counting(Arc<Counter>);
+
+  unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+      self.0.allocations.fetch_add(1, Ordering::SeqCst);
+      return System.alloc(layout);
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+      self.0.deallocations.fetch_add(1, Ordering::SeqCst);
+      System.dealloc(ptr, layout);
+    }
+  }
+
+  impl Drop for Counting {
+    fn drop(&mut self) {
+      self.0.dropped.store(true, Ordering::SeqCst);
+    }
+  }
+
+  // Forwards to the system allocator, counting the blocks it has out.
+  #[derive(Default)]
+  struct Blocks(AtomicUsize);
+
+  unsafe impl CFAllocatorCallbacks for Blocks {
+    fn allocate(&self, size: CFIndex, hint: CFOptionFlags) -> *mut c_void {
+      self.0.fetch_add(1, Ordering::SeqCst);
+      return unsafe { CFAllocatorAllocate(Some(&kCFAllocatorSystemDefault), size, hint) };
+    }
+
+    fn deallocate(&self, ptr: *mut c_void) {
+      self.0.fetch_sub(1, Ordering::SeqCst);
+      unsafe { CFAllocatorDeallocate(Some(&kCFAllocatorSystemDefault), ptr) };
+    }
+  }
+
+  #[test]
+  fn it_reallocates_with_the_system_allocator() {
+    unsafe {
+      let mut context = mem::zeroed::<CFAllocatorContext>();
+
+      CFAllocatorGetContext(Some(&kCFAllocatorSystemDefault), &mut context);
+
+      let reallocate = context.reallocate.unwrap();
+      let block = reallocate(ptr::null_mut(), 8, CFOptionFlags(0), context.info);
+
+      assert!(!block.is_null());
+      assert!(reallocate(block, -1, CFOptionFlags(0), context.info).is_null());
+      assert!(reallocate(block, CFIndex::MAX, CFOptionFlags(0), context.info).is_null());
+
+      context.deallocate.unwrap()(block, context.info);
+    }
+  }
+
+  #[test]
+  fn it_keeps_sizes_the_system_allocator_cannot_round() {
+    assert_eq!(-1, CFAllocatorGetPreferredSizeForSize(Some(&kCFAllocatorSystemDefault), -1, CFOptionFlags(0)));
+    assert_eq!(CFIndex::MAX, CFAllocatorGetPreferredSizeForSize(Some(&kCFAllocatorSystemDefault), CFIndex::MAX, CFOptionFlags(0)));
+  }
+
+  #[test]
+  fn it_builds_allocators_from_callbacks() {
+    let builder = CFAllocatorBuilder::new(Blocks::default());
+    let info = builder.context().info;
+    let allocator = builder.allocator(&kCFAllocatorUseContext).create().unwrap();
+    let blocks = unsafe { &*(info as *const Blocks) };
+    let string = CFStringCreateWithCString(Some(&allocator), c"hagane", CFStringEncoding::kCFStringEncodingUTF8).unwrap();
+
+    assert_eq!(2, blocks.0.load(Ordering::SeqCst));
+    assert_eq!(7, CFAllocatorGetPreferredSizeForSize(Some(&allocator), 7, CFOptionFlags(0)));
+
+    unsafe {
+      let block = CFAllocatorAllocate(Some(&allocator), 8, CFOptionFlags(0));
+
+      assert!(CFAllocatorReallocate(Some(&allocator), block, 16, CFOptionFlags(0)).is_null());
+      CFAllocatorDeallocate(Some(&allocator), block);
+    }
+
+    let mut context = unsafe { mem::zeroed::<CFAllocatorContext>() };
+
+    unsafe { CFAllocatorGetContext(Some(&allocator), &mut context) };
+
+    let description = unsafe { CFOwned::from_create_rule(context.copyDescription.unwrap()(context.info).unwrap()) };
+
+    assert_eq!(info, context.info);
+    assert!(String::from(&*description).contains("Blocks"));
+
+    drop(string);
+    assert_eq!(1, blocks.0.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn it_aligns_rust_allocations() {
+    let counter = Arc::new(Counter::default());
+    let allocator = CFAllocatorAlloc::new(&CFAllocatorCreateWithGlobalAlloc(kCFAllocatorDefault, Counting(counter.clone())).unwrap());
+
+    for align in [1, 2, 8, 16, 64, 4096] {
+      unsafe {
+        let layout = Layout::from_size_align(24, align).unwrap();
+        let ptr = allocator.alloc(layout);
+
+        assert_eq!(0, ptr as usize % align);
+        ptr::copy_nonoverlapping(b"hagane core foundation!!".as_ptr(), ptr, 24);
+
+        let ptr = allocator.realloc(ptr, layout, 8192);
+
+        assert_eq!(0, ptr as usize % align);
+        assert_eq!(b"hagane core foundation!!", &*(ptr as *const [u8; 24]));
+
+        allocator.dealloc(ptr, Layout::from_size_align(8192, align).unwrap());
+      }
+    }
+
+    assert_eq!(counter.allocations.load(Ordering::SeqCst), counter.deallocations.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn it_keeps_the_default_it_was_made_with() {
+    let counter = Arc::new(Counter::default());
+    let counting = CFAllocatorCreateWithGlobalAlloc(kCFAllocatorDefault, Counting(counter.clone())).unwrap();
+    let allocator = with_default_allocator(&*counting, CFAllocatorAlloc::default);
+
+    assert!(CFEqual(&*counting, allocator.allocator()));
+
+    unsafe {
+      let layout = Layout::from_size_align(32, 8).unwrap();
+      let ptr = allocator.alloc(layout);
+
+      allocator.dealloc(with_default_allocator(&*kCFAllocatorSystemDefault, || allocator.realloc(ptr, layout, 64)), Layout::from_size_align(64, 8).unwrap());
+    }
+
+    assert!(counter.allocations.load(Ordering::SeqCst) > 0);
+    assert_eq!(counter.allocations.load(Ordering::SeqCst), counter.deallocations.load(Ordering::SeqCst));
+  }
+
+  #[cfg(feature = "allocator-api2")]
+  #[test]
+  fn it_backs_collections() {
+    let counter = Arc::new(Counter::default());
+    let allocator = CFAllocatorAlloc::new(&CFAllocatorCreateWithGlobalAlloc(kCFAllocatorDefault, Counting(counter.clone())).unwrap());
+    let mut values = allocator_api2::vec::Vec::new_in(allocator.clone());
+
+    values.extend(0..1000u64);
+
+    assert_eq!(499500, values.iter().sum::<u64>());
+    assert!(counter.allocations.load(Ordering::SeqCst) > 0);
+
+    drop(values);
+    drop(allocator);
+    assert!(counter.dropped.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn it_allocates_with_global_allocators() {
+    let counter = Arc::new(Counter::default());
+    let allocator = CFAllocatorCreateWithGlobalAlloc(kCFAllocatorDefault, Counting(counter.clone())).unwrap();
+    let string = CFStringCreateWithCString(Some(&allocator), c"hagane", CFStringEncoding::kCFStringEncodingUTF8).unwrap();
+
+    assert!(counter.allocations.load(Ordering::SeqCst) > 0);
+    assert!(CFEqual(&*allocator, &*CFGetAllocator(&*string)));
+    assert_eq!(32, CFAllocatorGetPreferredSizeForSize(Some(&allocator), 17, CFOptionFlags(0)));
+    assert_eq!(CFIndex::MAX, CFAllocatorGetPreferredSizeForSize(Some(&allocator), CFIndex::MAX, CFOptionFlags(0)));
+
+    unsafe {
+      let block = CFAllocatorReallocate(Some(&allocator), CFAllocatorAllocate(Some(&allocator), 8, CFOptionFlags(0)), 64, CFOptionFlags(0));
+
+      assert!(!block.is_null());
+      CFAllocatorDeallocate(Some(&allocator), block);
+    }
+
+    drop(allocator);
+    assert!(!counter.dropped.load(Ordering::SeqCst));
+
+    drop(string);
+    assert!(counter.dropped.load(Ordering::SeqCst));
+    assert_eq!(counter.allocations.load(Ordering::SeqCst), counter.deallocations.load(Ordering::SeqCst));
+  }
+}
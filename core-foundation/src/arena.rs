@@ -0,0 +1,327 @@
+// An allocator for batches of short-lived objects: blocks are carved out of large chunks one after the other,
+// deallocating does nothing, and the chunks are all given back at once when the arena is reset.
+
+use crate::*;
+
+use std::alloc::{GlobalAlloc, Layout};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+pub const kCFArenaDefaultChunkSize: usize = 64 * 1024;
+
+struct Chunk {
+  memory: NonNull<u8>,
+  size: usize
+}
+
+impl Chunk {
+  fn layout(&self) -> Layout {
+    return unsafe { Layout::from_size_align_unchecked(self.size, kCFAllocatorBlockAlignment) };
+  }
+
+  fn offset(&self, block: *mut u8) -> Option<usize> {
+    return (block as usize).checked_sub(self.memory.as_ptr() as usize).filter(|offset| *offset < self.size);
+  }
+}
+
+// Blocks are prefixed with their size, so that reallocating knows how much to copy. In debug mode, the blocks not
+// yet deallocated are kept by address.
+struct State {
+  source: CFAllocatorAlloc,
+  chunk_size: usize,
+  chunks: Vec<Chunk>,
+  used: usize,
+  live: Option<BTreeMap<usize, usize>>
+}
+
+// Chunks are only touched with the arena locked.
+unsafe impl Send for State { }
+
+// The space a block of `size` bytes takes up in a chunk, with its header.
+fn footprint(size: usize) -> Option<usize> {
+  let alignment = kCFAllocatorBlockAlignment;
+
+  return size.checked_add(2 * alignment - 1).map(|size| size / alignment * alignment);
+}
+
+impl State {
+  // Takes `padded` bytes from the current chunk, or a new one once it is full.
+  fn bump(&mut self, padded: usize) -> *mut u8 {
+    if let Some(chunk) = self.chunks.last() {
+      if chunk.size - self.used >= padded {
+        let block = unsafe { chunk.memory.as_ptr().add(self.used) };
+
+        self.used += padded;
+
+        return block;
+      }
+    }
+
+    let size = self.chunk_size.max(padded);
+    let memory = match Layout::from_size_align(size, kCFAllocatorBlockAlignment) {
+      Ok(layout) => NonNull::new(unsafe { self.source.alloc(layout) }),
+      Err(_) => None
+    };
+    let memory = match memory {
+      Some(memory) => memory,
+      None => return ptr::null_mut()
+    };
+
+    self.chunks.push(Chunk { memory, size });
+    self.used = padded;
+
+    return memory.as_ptr();
+  }
+
+  // Gives back the chunks `keep` turns down.
+  fn free(&mut self, mut keep: impl FnMut(&Chunk) -> bool) {
+    let source = &self.source;
+
+    self.chunks.retain(|chunk| keep(chunk) || unsafe { source.dealloc(chunk.memory.as_ptr(), chunk.layout()); false });
+  }
+
+  unsafe fn place(&mut self, block: *mut u8, size: usize) -> *mut c_void {
+    let ptr = block.add(kCFAllocatorBlockAlignment);
+
+    *(block as *mut usize) = size;
+
+    if let Some(live) = &mut self.live {
+      live.insert(ptr as usize, size);
+    }
+
+    return ptr as *mut c_void;
+  }
+}
+
+impl Drop for State {
+  fn drop(&mut self) {
+    self.free(|_| false);
+  }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+  return state.lock().unwrap_or_else(PoisonError::into_inner);
+}
+
+struct Arena(Arc<Mutex<State>>);
+
+unsafe impl CFAllocatorCallbacks for Arena {
+  fn allocate(&self, size: CFIndex, _hint: CFOptionFlags) -> *mut c_void {
+    let padded = match footprint(size as usize) {
+      Some(padded) => padded,
+      None => return ptr::null_mut()
+    };
+    let mut state = lock(&self.0);
+    let block = state.bump(padded);
+
+    if block.is_null() {
+      return ptr::null_mut();
+    }
+
+    return unsafe { state.place(block, size as usize) };
+  }
+
+  // The block allocated last grows or shrinks where it is, as long as its chunk has room; any other moves.
+  fn reallocate(&self, ptr: *mut c_void, size: CFIndex, _hint: CFOptionFlags) -> *mut c_void {
+    let padded = match footprint(size as usize) {
+      Some(padded) => padded,
+      None => return ptr::null_mut()
+    };
+    let mut state = lock(&self.0);
+    let block = unsafe { (ptr as *mut u8).sub(kCFAllocatorBlockAlignment) };
+    let previous = unsafe { *(block as *const usize) };
+
+    let last = state.chunks.last().and_then(|chunk| Some((chunk.offset(block)?, chunk.size)));
+
+    if let Some((offset, chunk_size)) = last {
+      if footprint(previous).is_some_and(|footprint| offset + footprint == state.used) && chunk_size - offset >= padded {
+        state.used = offset + padded;
+
+        return unsafe { state.place(block, size as usize) };
+      }
+    }
+
+    let moved = state.bump(padded);
+
+    if moved.is_null() {
+      return ptr::null_mut();
+    }
+
+    if let Some(live) = &mut state.live {
+      live.remove(&(ptr as usize));
+    }
+
+    unsafe {
+      ptr::copy_nonoverlapping(ptr as *const u8, moved.add(kCFAllocatorBlockAlignment), previous.min(size as usize));
+
+      return state.place(moved, size as usize);
+    }
+  }
+
+  fn deallocate(&self, ptr: *mut c_void) {
+    if let Some(live) = &mut lock(&self.0).live {
+      live.remove(&(ptr as usize));
+    }
+  }
+
+  fn description(&self) -> String {
+    return format!("<CFArenaAllocator chunks of {} bytes>", lock(&self.0).chunk_size);
+  }
+}
+
+pub struct CFArenaAllocator {
+  allocator: CFOwned<CFAllocatorRef>,
+  state: Arc<Mutex<State>>
+}
+
+impl CFArenaAllocator {
+  // Blocks larger than `chunk_size` get a chunk of their own. Chunks come from the system allocator.
+  pub fn new(chunk_size: usize) -> Option<CFArenaAllocator> {
+    return CFArenaAllocator::create(&kCFAllocatorSystemDefault, chunk_size, false);
+  }
+
+  // Also keeps track of the blocks not yet deallocated, which belong to objects still referenced, so that resetting
+  // with any left panics instead of freeing them.
+  pub fn debug(chunk_size: usize) -> Option<CFArenaAllocator> {
+    return CFArenaAllocator::create(&kCFAllocatorSystemDefault, chunk_size, true);
+  }
+
+  // Takes chunks from `source`, which also provides the allocator itself so that it outlives resets. They are all
+  // given back once the arena and every object made with it have been released.
+  pub fn create(source: &CFAllocatorRef, chunk_size: usize, debug: bool) -> Option<CFArenaAllocator> {
+    let live = if debug { Some(BTreeMap::new()) } else { None };
+    let state = Arc::new(Mutex::new(State { source: CFAllocatorAlloc::new(source), chunk_size, chunks: Vec::new(), used: 0, live }));
+    let allocator = CFAllocatorBuilder::new(Arena(state.clone())).allocator(source).create()?;
+
+    return Some(CFArenaAllocator { allocator, state });
+  }
+
+  pub fn allocator(&self) -> &CFAllocatorRef {
+    return &self.allocator;
+  }
+
+  // Bytes taken from chunks since the last reset, with headers and padding, and the ends of chunks that were full.
+  pub fn used_bytes(&self) -> usize {
+    let state = lock(&self.state);
+
+    return state.chunks.iter().rev().skip(1).map(|chunk| chunk.size).sum::<usize>() + state.used;
+  }
+
+  // Frees every block at once, keeping a chunk to start over with. Nothing allocated from the arena may be used
+  // afterwards, which debug mode checks for the objects that still hold their memory.
+  pub unsafe fn reset(&self) {
+    let mut state = lock(&self.state);
+
+    if let Some(live) = state.live.as_ref().filter(|live| !live.is_empty()) {
+      let mut report = String::new();
+
+      for (address, size) in live.iter() {
+        let _ = write!(report, "\n{:#x} ({} bytes)", address, size);
+      }
+
+      drop(state);
+      panic!("arena reset while blocks were still referenced:{}", report);
+    }
+
+    let chunk_size = state.chunk_size;
+    let mut kept = false;
+
+    state.free(|chunk| chunk.size == chunk_size && !mem::replace(&mut kept, true));
+    state.used = 0;
+  }
+
+  // Allocates everything made with the default allocator on this thread from the arena while `body` runs.
+  pub fn install<R>(&self, body: impl FnOnce() -> R) -> R {
+    return with_default_allocator(&*self.allocator, body);
+  }
+}
+
+impl fmt::Debug for CFArenaAllocator {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return f.debug_struct("CFArenaAllocator").field("allocator", &self.allocator).field("used_bytes", &self.used_bytes()).finish();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::*;
+
+  use std::panic::{self, AssertUnwindSafe};
+
+  fn create(allocator: &CFArenaAllocator, contents: &str) -> CFOwned<CFStringRef> {
+    return CFStringCreateWithBytes(Some(allocator.allocator()), contents.as_bytes(), CFStringEncoding::kCFStringEncodingUTF8, false).unwrap();
+  }
+
+  #[test]
+  fn it_allocates_from_chunks() {
+    let arena = CFArenaAllocator::new(256).unwrap();
+    let strings = (0..100).map(|i| create(&arena, &format!("string {}", i))).collect::<Vec<_>>();
+
+    assert_eq!("string 42", String::from(&*strings[42]));
+    assert!(arena.used_bytes() > 256);
+
+    drop(strings);
+    unsafe { arena.reset() };
+    assert_eq!(0, arena.used_bytes());
+
+    let string = arena.install(|| CFStringCreateWithCString(kCFAllocatorDefault, c"hagane", CFStringEncoding::kCFStringEncodingUTF8).unwrap());
+
+    assert!(CFEqual(arena.allocator(), &*CFGetAllocator(&*string)));
+    assert!(arena.used_bytes() > 0);
+  }
+
+  #[test]
+  fn it_frees_chunks_once_released() {
+    let source = CFStatisticsAllocator::new(Some(&kCFAllocatorSystemDefault)).unwrap();
+    let arena = CFArenaAllocator::create(source.allocator(), 256, false).unwrap();
+    let strings = arena.install(|| (0..100).map(|i| CFStringCreateWithBytes(kCFAllocatorDefault, format!("string {}", i).as_bytes(), CFStringEncoding::kCFStringEncodingUTF8, false).unwrap()).collect::<Vec<_>>());
+
+    assert!(source.statistics().allocations > 2);
+
+    drop(arena);
+    assert!(source.statistics().live_bytes > 0);
+
+    drop(strings);
+
+    let statistics = source.statistics();
+
+    assert_eq!(0, statistics.live_bytes);
+    assert_eq!(statistics.allocations, statistics.deallocations);
+  }
+
+  #[test]
+  fn it_reallocates_in_place() {
+    let arena = CFArenaAllocator::new(kCFArenaDefaultChunkSize).unwrap();
+
+    unsafe {
+      let first = CFAllocatorAllocate(Some(arena.allocator()), 8, CFOptionFlags(0));
+
+      ptr::copy_nonoverlapping(b"hagane!!".as_ptr(), first as *mut u8, 8);
+
+      let grown = CFAllocatorReallocate(Some(arena.allocator()), first, 1000, CFOptionFlags(0));
+      let second = CFAllocatorAllocate(Some(arena.allocator()), 8, CFOptionFlags(0));
+      let moved = CFAllocatorReallocate(Some(arena.allocator()), grown, 2000, CFOptionFlags(0));
+
+      assert_eq!(first, grown);
+      assert!(moved > second);
+      assert_eq!(b"hagane!!", &*(moved as *const [u8; 8]));
+    }
+  }
+
+  #[test]
+  fn it_finds_objects_referenced_at_reset() {
+    let arena = CFArenaAllocator::debug(kCFArenaDefaultChunkSize).unwrap();
+    let kept = create(&arena, "kept");
+
+    drop(create(&arena, "released"));
+
+    let panic = panic::catch_unwind(AssertUnwindSafe(|| unsafe { arena.reset() })).unwrap_err();
+
+    assert!(panic.downcast_ref::<String>().unwrap().starts_with("arena reset while blocks were still referenced"));
+    assert_eq!("kept", String::from(&*kept));
+
+    drop(kept);
+    unsafe { arena.reset() };
+  }
+}
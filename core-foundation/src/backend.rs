@@ -0,0 +1,199 @@
+// Routes the calls wrappers make into CoreFoundation through a `CFBackend`, so that tests can stand in for the
+// framework: recording calls, checking their order, or making them fail. Enabled by the `backend` feature.
+
+use crate::*;
+
+use std::cell::Cell;
+
+// Declares CFBackend with a method for each function of each `ext` module, defaulting to the function itself, and a
+// module of the same functions that call the current backend for the wrappers to use.
+macro_rules! backend {
+  ($($module:ident in $ext:path { $(fn $name:ident($($argument:ident: $type:ty),*) $(-> $result:ty)?;)* })*) => {
+    pub trait CFBackend {
+      $($(
+        unsafe fn $name(&self, $($argument: $type),*) $(-> $result)? {
+          use $ext as ext;
+
+          return ext::$name($($argument),*);
+        }
+      )*)*
+    }
+
+    $(
+      pub(crate) mod $module {
+        use crate::*;
+        use crate::backend::{self, CFBackend};
+
+        $(
+          pub unsafe fn $name($($argument: $type),*) $(-> $result)? {
+            return match backend::current() {
+              Some(backend) => (*backend).$name($($argument),*),
+              None => backend::Native.$name($($argument),*)
+            };
+          }
+        )*
+      }
+    )*
+  };
+}
+
+backend! {
+  base in crate::ext {
+    fn CFCopyTypeIDDescription(type_id: CFTypeID) -> Option<CFStringRef>;
+  }
+
+  allocator in crate::allocator::ext {
+    fn CFAllocatorGetTypeID() -> CFTypeID;
+    fn CFAllocatorSetDefault(allocator: CFAllocatorRef);
+    fn CFAllocatorGetDefault() -> CFAllocatorRef;
+    fn CFAllocatorCreate(allocator: Option<CFAllocatorRef>, context: *mut CFAllocatorContext) -> Option<CFAllocatorRef>;
+    fn CFAllocatorAllocate(allocator: Option<CFAllocatorRef>, size: CFIndex, hint: CFOptionFlags) -> *mut c_void;
+    fn CFAllocatorReallocate(allocator: Option<CFAllocatorRef>, ptr: *mut c_void, newsize: CFIndex, hint: CFOptionFlags) -> *mut c_void;
+    fn CFAllocatorDeallocate(allocator: Option<CFAllocatorRef>, ptr: *mut c_void);
+    fn CFAllocatorGetPreferredSizeForSize(allocator: Option<CFAllocatorRef>, size: CFIndex, hint: CFOptionFlags) -> CFIndex;
+    fn CFAllocatorGetContext(allocator: Option<CFAllocatorRef>, context: *mut CFAllocatorContext);
+  }
+
+  null in crate::null::ext {
+    fn CFNullGetTypeID() -> CFTypeID;
+  }
+
+  object in crate::object::ext {
+    fn CFGetTypeID(cf: CFTypeRef) -> CFTypeID;
+    fn CFRetain(cf: CFTypeRef) -> CFTypeRef;
+    fn CFRelease(cf: CFTypeRef);
+    fn CFAutorelease(arg: CFTypeRef) -> CFTypeRef;
+    fn CFGetRetainCount(cf: CFTypeRef) -> CFIndex;
+    fn CFEqual(cf1: CFTypeRef, cf2: CFTypeRef) -> Boolean;
+    fn CFHash(cf: CFTypeRef) -> CFHashCode;
+    fn CFCopyDescription(cf: CFTypeRef) -> Option<CFStringRef>;
+    fn CFGetAllocator(cf: CFTypeRef) -> CFAllocatorRef;
+    fn CFShow(obj: CFTypeRef);
+  }
+
+  runtime in crate::runtime::ext {
+    fn _CFRuntimeRegisterClass(cls: *const CFRuntimeClass) -> CFTypeID;
+    fn _CFRuntimeCreateInstance(allocator: Option<CFAllocatorRef>, typeID: CFTypeID, extraBytes: CFIndex, category: *mut u8) -> Option<CFTypeRef>;
+  }
+
+  string in crate::string::ext {
+    fn CFStringGetTypeID() -> CFTypeID;
+    fn CFStringCreateWithCString(alloc: Option<CFAllocatorRef>, cStr: *const c_char, encoding: CFStringEncoding) -> Option<CFStringRef>;
+    fn CFStringCreateWithBytes(alloc: Option<CFAllocatorRef>, bytes: *const UInt8, numBytes: CFIndex, encoding: CFStringEncoding, isExternalRepresentation: Boolean) -> Option<CFStringRef>;
+    fn CFStringGetLength(theString: CFStringRef) -> CFIndex;
+    fn CFStringGetCString(theString: CFStringRef, buffer: *mut c_char, bufferSize: CFIndex, encoding: CFStringEncoding) -> Boolean;
+    fn CFStringGetBytes(theString: CFStringRef, range: CFRange, encoding: CFStringEncoding, lossByte: UInt8, isExternalRepresentation: Boolean, buffer: *mut UInt8, maxBufLen: CFIndex, usedBufLen: *mut CFIndex) -> CFIndex;
+    fn CFStringGetMaximumSizeForEncoding(length: CFIndex, encoding: CFStringEncoding) -> CFIndex;
+    fn CFShowStr(string: CFStringRef);
+  }
+}
+
+// Calls straight into CoreFoundation, or the portable implementation. Backends can forward to it for the calls they
+// do not mean to change.
+pub struct Native;
+
+impl CFBackend for Native { }
+
+thread_local! {
+  static Current: Cell<Option<*const dyn CFBackend>> = const { Cell::new(None) };
+}
+
+pub(crate) fn current() -> Option<*const dyn CFBackend> {
+  return Current.with(Cell::get);
+}
+
+struct Restore(Option<*const dyn CFBackend>);
+
+impl Drop for Restore {
+  fn drop(&mut self) {
+    Current.with(|current| current.set(self.0));
+  }
+}
+
+// Runs `body` with every call this thread makes into CoreFoundation going to `backend`. Objects a backend makes up must
+// not outlive it, as releasing them afterwards goes to the framework.
+pub fn with_backend<B: CFBackend, R>(backend: &B, body: impl FnOnce() -> R) -> R {
+  let backend: &dyn CFBackend = backend;
+  let backend: *const (dyn CFBackend + '_) = backend;
+  let _restore = Restore(Current.with(|current| current.replace(Some(unsafe { mem::transmute::<*const (dyn CFBackend + '_), *const dyn CFBackend>(backend) }))));
+
+  return body();
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::*;
+  use crate::backend::{with_backend, CFBackend, Native};
+
+  use std::cell::RefCell;
+
+  // Describes nothing, as CoreFoundation does when it runs out of memory.
+  struct Undescribed;
+
+  impl CFBackend for Undescribed {
+    unsafe fn CFCopyDescription(&self, _cf: CFTypeRef) -> Option<CFStringRef> {
+      return None;
+    }
+  }
+
+  #[derive(Default)]
+  struct Recorder {
+    calls: RefCell<Vec<&'static str>>
+  }
+
+  impl CFBackend for Recorder {
+    unsafe fn CFCopyDescription(&self, cf: CFTypeRef) -> Option<CFStringRef> {
+      self.calls.borrow_mut().push("CFCopyDescription");
+      return Native.CFCopyDescription(cf);
+    }
+
+    unsafe fn CFCopyTypeIDDescription(&self, _type_id: CFTypeID) -> Option<CFStringRef> {
+      self.calls.borrow_mut().push("CFCopyTypeIDDescription");
+      return None;
+    }
+
+    unsafe fn CFRelease(&self, cf: CFTypeRef) {
+      self.calls.borrow_mut().push("CFRelease");
+      Native.CFRelease(cf);
+    }
+
+    unsafe fn CFStringCreateWithCString(&self, _alloc: Option<CFAllocatorRef>, _cStr: *const c_char, _encoding: CFStringEncoding) -> Option<CFStringRef> {
+      self.calls.borrow_mut().push("CFStringCreateWithCString");
+      return None;
+    }
+  }
+
+  #[test]
+  fn it_records_calls() {
+    let recorder = Recorder::default();
+    let description = with_backend(&recorder, || format!("{:?}", kCFAllocatorSystemDefault));
+
+    assert!(description.starts_with("<CFAllocator"));
+    assert_eq!(vec!["CFCopyDescription", "CFRelease"], *recorder.calls.borrow());
+  }
+
+  #[test]
+  fn it_injects_failures() {
+    let recorder = Recorder::default();
+
+    assert!(with_backend(&recorder, || CFStringCreateWithCString(kCFAllocatorDefault, c"hagane", CFStringEncoding::kCFStringEncodingUTF8)).is_none());
+    assert!(CFStringCreateWithCString(kCFAllocatorDefault, c"hagane", CFStringEncoding::kCFStringEncodingUTF8).is_some());
+    assert_eq!(vec!["CFStringCreateWithCString"], *recorder.calls.borrow());
+  }
+
+  #[test]
+  fn it_describes_types_without_descriptions() {
+    let recorder = Recorder::default();
+    let error = CFDowncastError { expected: CFTypeID(42), actual: CFNullGetTypeID(), description: String::from("CFNull") };
+
+    assert_eq!("expected an object of type ID 42, found CFNull", with_backend(&recorder, || error.to_string()));
+    assert_eq!(vec!["CFCopyTypeIDDescription"], *recorder.calls.borrow());
+  }
+
+  #[test]
+  fn it_formats_objects_without_descriptions() {
+    let expected = format!("<{} {:p}>", CFNullGetTypeID().0, kCFNull.0);
+
+    assert_eq!(expected, with_backend(&Undescribed, || format!("{:?}", kCFNull)));
+  }
+}
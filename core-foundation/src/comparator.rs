@@ -0,0 +1,143 @@
+use crate::*;
+
+use std::any::Any;
+use std::cmp::Ordering;
+use std::panic::{self, AssertUnwindSafe};
+
+// Declared with CF_ENUM(CFIndex, ...), so as wide as a CFIndex.
+#[cfg_attr(target_pointer_width = "64", repr(i64))]
+#[cfg_attr(target_pointer_width = "32", repr(i32))]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum CFComparisonResult {
+  kCFCompareLessThan = -1,
+  kCFCompareEqualTo = 0,
+  kCFCompareGreaterThan = 1
+}
+
+pub type CFComparatorFunction = unsafe extern "C" fn(val1: *const c_void, val2: *const c_void, context: *mut c_void) -> CFComparisonResult;
+
+impl From<Ordering> for CFComparisonResult {
+  fn from(ordering: Ordering) -> CFComparisonResult {
+    return match ordering {
+      Ordering::Less => CFComparisonResult::kCFCompareLessThan,
+      Ordering::Equal => CFComparisonResult::kCFCompareEqualTo,
+      Ordering::Greater => CFComparisonResult::kCFCompareGreaterThan
+    };
+  }
+}
+
+impl From<CFComparisonResult> for Ordering {
+  fn from(result: CFComparisonResult) -> Ordering {
+    return match result {
+      CFComparisonResult::kCFCompareLessThan => Ordering::Less,
+      CFComparisonResult::kCFCompareEqualTo => Ordering::Equal,
+      CFComparisonResult::kCFCompareGreaterThan => Ordering::Greater
+    };
+  }
+}
+
+struct Comparator<T, F> {
+  compare: F,
+  panic: Option<Box<dyn Any + Send>>,
+  values: PhantomData<fn(&T, &T)>
+}
+
+// Panics cannot unwind through CoreFoundation, so the first is kept to be resumed once it has returned, and every
+// comparison after it reports equality. NULL values, which have no handle, sort first.
+unsafe extern "C" fn trampoline<T: Subtype<CFTypeRef>, F: FnMut(&T, &T) -> Ordering>(val1: *const c_void, val2: *const c_void, context: *mut c_void) -> CFComparisonResult {
+  let comparator = &mut *(context as *mut Comparator<T, F>);
+
+  if comparator.panic.is_some() {
+    return CFComparisonResult::kCFCompareEqualTo;
+  }
+
+  if val1.is_null() || val2.is_null() {
+    return CFComparisonResult::from(val2.is_null().cmp(&val1.is_null()));
+  }
+
+  let val1 = &*(&val1 as *const *const c_void as *const T);
+  let val2 = &*(&val2 as *const *const c_void as *const T);
+
+  return match panic::catch_unwind(AssertUnwindSafe(|| (comparator.compare)(val1, val2))) {
+    Ok(ordering) => CFComparisonResult::from(ordering),
+    Err(panic) => {
+      comparator.panic = Some(panic);
+      CFComparisonResult::kCFCompareEqualTo
+    }
+  };
+}
+
+// Hands `body` a comparator function and context that call `compare` on the values being compared, read as handles
+// of type `T`, like the CF objects in a collection. A panic in `compare` is resumed once `body` returns.
+pub fn with_comparator<T: Subtype<CFTypeRef>, F: FnMut(&T, &T) -> Ordering, R>(compare: F, body: impl FnOnce(CFComparatorFunction, *mut c_void) -> R) -> R {
+  let mut comparator = Comparator { compare, panic: None, values: PhantomData::<fn(&T, &T)> };
+  let result = body(trampoline::<T, F>, &mut comparator as *mut Comparator<T, F> as *mut c_void);
+
+  if let Some(panic) = comparator.panic {
+    panic::resume_unwind(panic);
+  }
+
+  return result;
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::*;
+
+  use std::cmp::Ordering;
+  use std::panic;
+
+  // Sorts the way CFArraySortValues does, through a comparator function and its context.
+  unsafe fn sort(values: &mut [*const c_void], comparator: CFComparatorFunction, context: *mut c_void) {
+    for i in 1..values.len() {
+      let mut j = i;
+
+      while j > 0 && comparator(values[j - 1], values[j], context) == CFComparisonResult::kCFCompareGreaterThan {
+        values.swap(j - 1, j);
+        j -= 1;
+      }
+    }
+  }
+
+  #[test]
+  fn it_converts_orderings() {
+    assert_eq!(CFComparisonResult::kCFCompareLessThan, CFComparisonResult::from(1.cmp(&2)));
+    assert_eq!(Ordering::Greater, Ordering::from(CFComparisonResult::kCFCompareGreaterThan));
+  }
+
+  #[test]
+  fn it_sorts_with_closures() {
+    let strings = ["b", "c", "a"].map(|string| CFStringCreateWithBytes(kCFAllocatorDefault, string.as_bytes(), CFStringEncoding::kCFStringEncodingUTF8, false).unwrap());
+    let mut values = strings.each_ref().map(|string| string.0.as_ptr() as *const c_void);
+    let mut comparisons = 0;
+
+    with_comparator(|a: &CFStringRef, b: &CFStringRef| {
+      comparisons += 1;
+      String::from(a).cmp(&String::from(b))
+    }, |comparator, context| unsafe { sort(&mut values, comparator, context) });
+
+    assert_eq!([&strings[2], &strings[0], &strings[1]].map(|string| string.0.as_ptr() as *const c_void), values);
+    assert!(comparisons > 0);
+  }
+
+  #[test]
+  fn it_sorts_nulls_first() {
+    let string = CFStringCreateWithCString(kCFAllocatorDefault, c"hagane", CFStringEncoding::kCFStringEncodingUTF8).unwrap();
+    let mut values = [string.0.as_ptr() as *const c_void, ptr::null()];
+
+    with_comparator(|_: &CFStringRef, _: &CFStringRef| -> Ordering { panic!("compared a NULL value") }, |comparator, context| unsafe { sort(&mut values, comparator, context) });
+
+    assert_eq!([ptr::null(), string.0.as_ptr() as *const c_void], values);
+  }
+
+  #[test]
+  fn it_catches_panics() {
+    let strings = ["b", "a"].map(|string| CFStringCreateWithBytes(kCFAllocatorDefault, string.as_bytes(), CFStringEncoding::kCFStringEncodingUTF8, false).unwrap());
+    let mut values = strings.each_ref().map(|string| string.0.as_ptr() as *const c_void);
+    let result = panic::catch_unwind(move || {
+      with_comparator(|_: &CFStringRef, _: &CFStringRef| -> Ordering { panic!("comparator failed") }, |comparator, context| unsafe { sort(&mut values, comparator, context) });
+    });
+
+    assert_eq!(Some(&"comparator failed"), result.unwrap_err().downcast_ref::<&str>());
+  }
+}
@@ -0,0 +1,201 @@
+use crate::*;
+
+use std::str::FromStr;
+
+// Four bytes read as characters in order, so the code for 'APPL' is 0x4150504C whatever the target's byte order.
+#[repr(transparent)] #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)] pub struct FourCharCode(u32);
+
+pub type OSType = FourCharCode;
+pub type ResType = FourCharCode;
+
+impl FourCharCode {
+  pub const fn from_bytes(bytes: [u8; 4]) -> FourCharCode {
+    return FourCharCode(u32::from_be_bytes(bytes));
+  }
+
+  pub const fn to_bytes(self) -> [u8; 4] {
+    return self.0.to_be_bytes();
+  }
+
+  // For codes stored as little-endian integers, as written by a plain memory copy on x86 and ARM targets.
+  pub const fn from_le_bytes(bytes: [u8; 4]) -> FourCharCode {
+    return FourCharCode(u32::from_le_bytes(bytes));
+  }
+
+  pub const fn to_le_bytes(self) -> [u8; 4] {
+    return self.0.to_le_bytes();
+  }
+}
+
+impl From<u32> for FourCharCode {
+  fn from(code: u32) -> FourCharCode {
+    return FourCharCode(code);
+  }
+}
+
+impl From<FourCharCode> for u32 {
+  fn from(code: FourCharCode) -> u32 {
+    return code.0;
+  }
+}
+
+// Printable ASCII is written as is, apart from backslashes, and any other byte as a \xHH escape.
+impl fmt::Display for FourCharCode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for byte in self.to_bytes() {
+      match byte {
+        b'\\' => f.write_str("\\\\")?,
+        b' '..=b'~' => write!(f, "{}", byte as char)?,
+        _ => write!(f, "\\x{:02X}", byte)?
+      }
+    }
+
+    return Ok(());
+  }
+}
+
+impl fmt::Debug for FourCharCode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return write!(f, "FourCharCode('{}')", self);
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFourCharCodeError {
+  pub input: String
+}
+
+impl fmt::Display for ParseFourCharCodeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return write!(f, "{:?} is not four printable ASCII characters or \\xHH escapes", self.input);
+  }
+}
+
+impl Error for ParseFourCharCodeError { }
+
+impl FromStr for FourCharCode {
+  type Err = ParseFourCharCodeError;
+
+  fn from_str(s: &str) -> Result<FourCharCode, ParseFourCharCodeError> {
+    let error = || ParseFourCharCodeError { input: String::from(s) };
+    let mut bytes = Vec::with_capacity(4);
+    let mut rest = s.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+      rest = tail;
+
+      if byte != b'\\' {
+        if !(b' '..=b'~').contains(&byte) {
+          return Err(error());
+        }
+
+        bytes.push(byte);
+        continue;
+      }
+
+      match rest {
+        [b'\\', tail @ ..] => {
+          bytes.push(b'\\');
+          rest = tail;
+        },
+        [b'x', high, low, tail @ ..] => {
+          let (Some(high), Some(low)) = ((*high as char).to_digit(16), (*low as char).to_digit(16)) else {
+            return Err(error());
+          };
+
+          bytes.push((high * 16 + low) as u8);
+          rest = tail;
+        },
+        _ => return Err(error())
+      }
+    }
+
+    return <[u8; 4]>::try_from(bytes).map(FourCharCode::from_bytes).map_err(|_| error());
+  }
+}
+
+// Human-readable formats such as property lists hold codes as strings, and compact ones as integers.
+#[cfg(feature = "serde")]
+impl serde::Serialize for FourCharCode {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+      return serializer.collect_str(self);
+    }
+
+    return serializer.serialize_u32(self.0);
+  }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for FourCharCode {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<FourCharCode, D::Error> {
+    struct Visitor;
+
+    impl<'de> serde::de::Visitor<'de> for Visitor {
+      type Value = FourCharCode;
+
+      fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return f.write_str("a four-char code as a string or an integer");
+      }
+
+      fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<FourCharCode, E> {
+        return value.parse().map_err(E::custom);
+      }
+
+      fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<FourCharCode, E> {
+        return u32::try_from(value).map(FourCharCode).map_err(|_| E::invalid_value(serde::de::Unexpected::Unsigned(value), &self));
+      }
+    }
+
+    if deserializer.is_human_readable() {
+      return deserializer.deserialize_any(Visitor);
+    }
+
+    return deserializer.deserialize_u32(Visitor);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::*;
+
+  #[test]
+  fn it_converts_bytes() {
+    const APPL: OSType = OSType::from_bytes(*b"APPL");
+
+    assert_eq!(0x4150504C, u32::from(APPL));
+    assert_eq!(*b"APPL", APPL.to_bytes());
+    assert_eq!(APPL, FourCharCode::from_le_bytes(*b"LPPA"));
+    assert_eq!(*b"LPPA", APPL.to_le_bytes());
+  }
+
+  #[test]
+  fn it_formats() {
+    assert_eq!("APPL", OSType::from_bytes(*b"APPL").to_string());
+    assert_eq!("a\\\\\\x00\\x7F", FourCharCode::from_bytes([b'a', b'\\', 0, 0x7f]).to_string());
+    assert_eq!("FourCharCode('fmt?')", format!("{:?}", FourCharCode::from(0x666d743f)));
+  }
+
+  #[test]
+  fn it_parses() {
+    let code = FourCharCode::from_bytes([b'a', b'\\', 0, 0xff]);
+
+    assert_eq!(Ok(code), code.to_string().parse());
+    assert_eq!(Ok(OSType::from_bytes(*b"TEXT")), "TEXT".parse());
+    assert!("APP".parse::<FourCharCode>().is_err());
+    assert!("APPLE".parse::<FourCharCode>().is_err());
+    assert!("AP\\q".parse::<FourCharCode>().is_err());
+    assert!("APP\u{e9}".parse::<FourCharCode>().is_err());
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn it_serializes() {
+    use serde_test::{assert_tokens, Configure, Token};
+
+    let code = OSType::from_bytes(*b"APPL");
+
+    assert_tokens(&code.readable(), &[Token::Str("APPL")]);
+    assert_tokens(&code.compact(), &[Token::U32(0x4150504C)]);
+  }
+}
@@ -0,0 +1,99 @@
+// Checks the types shared with CoreFoundation against the layouts its headers give them. Pointers, longs and so
+// CFIndex are eight bytes on the 64-bit targets (x86_64-apple-darwin, aarch64-apple-darwin, aarch64-apple-ios and
+// the portable x86_64 and aarch64 Linux targets) and four on the 32-bit ones (arm64_32-apple-watchos,
+// armv7k-apple-watchos, i386-apple-ios and i686-unknown-linux-gnu). The checks run at compile time, and
+// `scripts/check-targets.sh` runs `cargo check --target` for every one of those triples.
+
+use crate::*;
+
+use std::mem::{align_of, offset_of, size_of};
+
+#[cfg(target_pointer_width = "64")]
+const WORD: usize = 8;
+
+#[cfg(target_pointer_width = "32")]
+const WORD: usize = 4;
+
+const fn assert_layout<T>(size: usize, align: usize) {
+  assert!(size_of::<T>() == size, "size differs from the C declaration");
+  assert!(align_of::<T>() == align, "alignment differs from the C declaration");
+}
+
+// Scalars.
+const _: () = {
+  assert_layout::<CFIndex>(WORD, WORD);
+  assert_layout::<CFTypeID>(WORD, WORD);
+  assert_layout::<CFOptionFlags>(WORD, WORD);
+  assert_layout::<CFHashCode>(WORD, WORD);
+  assert_layout::<UniCharCount>(WORD, WORD);
+  assert_layout::<CFComparisonResult>(WORD, WORD);
+  assert_layout::<CFStringNormalizationForm>(WORD, WORD);
+
+  assert_layout::<Boolean>(1, 1);
+  assert_layout::<CFStringEncoding>(4, 4);
+  assert_layout::<OSStatus>(4, 4);
+  assert_layout::<OSErr>(2, 2);
+  assert_layout::<FourCharCode>(4, 4);
+};
+
+// Handles.
+const _: () = {
+  assert_layout::<CFTypeRef>(WORD, WORD);
+  assert_layout::<Option<CFStringRef>>(WORD, WORD);
+  assert_layout::<Option<CFOwned<CFAllocatorRef>>>(WORD, WORD);
+  assert_layout::<Option<CFComparatorFunction>>(WORD, WORD);
+};
+
+// Ranges.
+const _: () = {
+  assert_layout::<CFRange>(2 * WORD, WORD);
+  assert!(offset_of!(CFRange, location) == 0);
+  assert!(offset_of!(CFRange, length) == WORD);
+};
+
+// Allocator contexts.
+const _: () = {
+  assert_layout::<CFAllocatorContext>(9 * WORD, WORD);
+  // NULL callbacks are None, so every field stays a single pointer.
+  assert_layout::<Option<CFAllocatorRetainCallBack>>(WORD, WORD);
+  assert_layout::<Option<CFAllocatorReallocateCallBack>>(WORD, WORD);
+  assert!(offset_of!(CFAllocatorContext, version) == 0);
+  assert!(offset_of!(CFAllocatorContext, info) == WORD);
+  assert!(offset_of!(CFAllocatorContext, retain) == 2 * WORD);
+  assert!(offset_of!(CFAllocatorContext, release) == 3 * WORD);
+  assert!(offset_of!(CFAllocatorContext, copyDescription) == 4 * WORD);
+  assert!(offset_of!(CFAllocatorContext, allocate) == 5 * WORD);
+  assert!(offset_of!(CFAllocatorContext, reallocate) == 6 * WORD);
+  assert!(offset_of!(CFAllocatorContext, deallocate) == 7 * WORD);
+  assert!(offset_of!(CFAllocatorContext, preferredSize) == 8 * WORD);
+};
+
+// Runtime classes.
+const _: () = {
+  assert_layout::<CFRuntimeClass>(12 * WORD, WORD);
+  assert!(offset_of!(CFRuntimeClass, version) == 0);
+  assert!(offset_of!(CFRuntimeClass, className) == WORD);
+  assert!(offset_of!(CFRuntimeClass, init) == 2 * WORD);
+  assert!(offset_of!(CFRuntimeClass, copy) == 3 * WORD);
+  assert!(offset_of!(CFRuntimeClass, finalize) == 4 * WORD);
+  assert!(offset_of!(CFRuntimeClass, equal) == 5 * WORD);
+  assert!(offset_of!(CFRuntimeClass, hash) == 6 * WORD);
+  assert!(offset_of!(CFRuntimeClass, copyFormattingDesc) == 7 * WORD);
+  assert!(offset_of!(CFRuntimeClass, copyDebugDesc) == 8 * WORD);
+  assert!(offset_of!(CFRuntimeClass, reclaim) == 9 * WORD);
+  assert!(offset_of!(CFRuntimeClass, refcount) == 10 * WORD);
+  assert!(offset_of!(CFRuntimeClass, requiredAlignment) == 11 * WORD);
+};
+
+// Runtime bases. The framework's header is an isa pointer followed by four bytes of flags, and a 32-bit retain count
+// on 64-bit targets.
+#[cfg(target_vendor = "apple")]
+const _: () = {
+  assert_layout::<CFRuntimeBase>(if WORD == 8 { 16 } else { 8 }, WORD);
+};
+
+// Constant strings.
+#[cfg(target_vendor = "apple")]
+const _: () = {
+  assert_layout::<CFConstantString>(4 * WORD, WORD);
+};
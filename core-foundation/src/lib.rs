@@ -1,14 +1,59 @@
-#![feature(crate_in_paths)]
-#![feature(crate_visibility_modifier)]
-#![feature(repr_transparent)]
-
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 #![allow(non_upper_case_globals)]
+#![allow(clippy::missing_safety_doc)]
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_static_lifetimes)]
+#![allow(clippy::too_many_arguments)]
+
+// Constants are plain statics under the portable backend, so taking their address needs no `unsafe`.
+#![cfg_attr(not(target_vendor = "apple"), allow(unused_unsafe))]
 
 extern crate hagane_core;
 
+// Declares the `ext` block of an Apple target, linked against the framework or, with the `dlopen` feature, looked up
+// in it at run time.
+#[cfg(all(target_vendor = "apple", not(feature = "dlopen")))]
+macro_rules! cf_extern {
+  ($($item:tt)*) => {
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+      $($item)*
+    }
+  };
+}
+
+#[cfg(all(target_vendor = "apple", feature = "dlopen"))]
+macro_rules! cf_extern {
+  ($($item:tt)*) => {
+    crate::loader::dynamic_extern! {
+      crate::loader::CoreFoundation;
+
+      $($item)*
+    }
+  };
+}
+
+// Wraps a constant of an `ext` block in a CFConstant, which reads it where it is declared or, with the `dlopen`
+// feature, looks it up on first use.
+#[cfg(not(all(target_vendor = "apple", feature = "dlopen")))]
+macro_rules! cf_constant {
+  ($constant:path) => {
+    CFConstant::new(|| unsafe { &*ptr::addr_of!($constant) })
+  };
+}
+
+#[cfg(all(target_vendor = "apple", feature = "dlopen"))]
+macro_rules! cf_constant {
+  ($constant:path) => {
+    CFConstant::new(|| $constant.get())
+  };
+}
+
 mod allocator;
+mod arena;
+#[cfg(feature = "backend")]
+pub mod backend;
 // mod array;
 // mod attributed_string;
 // mod bag;
@@ -18,6 +63,7 @@ mod allocator;
 // mod byte_order;
 // mod calendar;
 // mod character_set;
+mod comparator;
 // mod data;
 // mod date;
 // mod date_formatter;
@@ -25,6 +71,10 @@ mod allocator;
 // mod error;
 // mod file_descriptor;
 // mod file_security;
+mod four_char_code;
+mod layout;
+#[cfg(feature = "dlopen")]
+pub mod loader;
 // mod locale;
 // mod mach_port;
 // mod message_port;
@@ -36,13 +86,19 @@ mod object;
 // mod plug_in;
 // mod preferences;
 // mod property_list;
+mod range;
 // mod run_loop;
+mod runtime;
 // mod set;
 // mod socket;
+mod statistics;
+mod status;
 // mod stream;
 mod string;
 // mod string_tokenizer;
 // mod time_zone;
+#[cfg(feature = "leak-tracker")]
+pub mod tracker;
 // mod tree;
 // mod url;
 // mod url_access;
@@ -53,22 +109,61 @@ mod string;
 // mod xml_node;
 // mod xml_parser;
 
+#[cfg(not(target_vendor = "apple"))]
+mod portable;
+
 pub use allocator::*;
+pub use arena::*;
+pub use comparator::*;
+pub use four_char_code::*;
 pub use null::*;
 pub use object::*;
+pub use range::*;
+pub use runtime::*;
+pub use statistics::*;
+pub use status::*;
 pub use string::*;
 
+use std::error::Error;
+use std::ffi::CStr;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
 use std::mem;
-use std::os::raw::c_void;
+use std::ops::Deref;
+use std::os::raw::{c_char, c_void};
+use std::ptr::{self, NonNull};
 
-use hagane_core::Subtype;
+use hagane_core::{cf_class, Subtype};
 
-#[derive(Debug, Eq, PartialEq)]
-#[repr(u8)] pub enum Boolean {
-  TRUE = 1,
-  FALSE = 0
+// Any nonzero byte is true, as in C, so whatever CoreFoundation returns is a valid Boolean.
+#[repr(transparent)] #[derive(Debug, Clone, Copy)] pub struct Boolean(u8);
+
+impl Boolean {
+  pub const TRUE: Boolean = Boolean(1);
+  pub const FALSE: Boolean = Boolean(0);
+}
+
+impl From<bool> for Boolean {
+  fn from(value: bool) -> Boolean {
+    return Boolean(value as u8);
+  }
 }
 
+impl From<Boolean> for bool {
+  fn from(value: Boolean) -> bool {
+    return value.0 != 0;
+  }
+}
+
+impl PartialEq for Boolean {
+  fn eq(&self, other: &Boolean) -> bool {
+    return bool::from(*self) == bool::from(*other);
+  }
+}
+
+impl Eq for Boolean { }
+
 pub type Byte = u8;
 pub type SignedByte = i8;
 
@@ -85,8 +180,14 @@ pub type SInt64 = i64;
 pub type Float32 = f32;
 pub type Float64 = f64;
 
+// The headers declare these as long, or long long under LLP64, which is pointer-sized on every supported target.
+#[cfg(target_pointer_width = "64")] type signed_long = i64;
+#[cfg(target_pointer_width = "64")] type unsigned_long = u64;
+#[cfg(target_pointer_width = "32")] type signed_long = i32;
+#[cfg(target_pointer_width = "32")] type unsigned_long = u32;
+
 pub type UniChar = u16;
-pub type UniCharCount = u64;
+pub type UniCharCount = unsigned_long;
 pub type StringPtr = *mut u8;
 pub type ConstStringPtr = *const u8;
 pub type Str255 = [u8; 256];
@@ -96,51 +197,89 @@ pub type UTF8Char = u8;
 pub type UTF16Char = u16;
 pub type UTF32Char = u32;
 
-pub type CFIndex = i64;
+pub type CFIndex = signed_long;
 
-#[repr(transparent)] pub struct OSStatus(i32);
-#[repr(transparent)] pub struct OSErr(i16);
 #[repr(transparent)] pub struct RegionCode(i16);
 #[repr(transparent)] pub struct LangCode(i16);
 #[repr(transparent)] pub struct ScriptCode(i16);
-#[repr(transparent)] pub struct FourCharCode(u32);
-#[repr(transparent)] pub struct OSType(u32);
 
-#[repr(transparent)] #[derive(PartialEq, Eq, Debug)] pub struct CFTypeID(u64);
-#[repr(transparent)] pub struct CFOptionFlags(u64);
-#[repr(transparent)] pub struct CFHashCode(u64);
+#[repr(transparent)] #[derive(PartialEq, Eq, Debug, Clone, Copy)] pub struct CFTypeID(unsigned_long);
+#[repr(transparent)] pub struct CFOptionFlags(unsigned_long);
+#[repr(transparent)] #[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)] pub struct CFHashCode(pub unsigned_long);
 
-#[repr(transparent)] pub struct CFPropertyListRef(usize);
+#[repr(transparent)] pub struct CFPropertyListRef(NonNull<c_void>);
 
-#[repr(i64)]
-pub enum CFComparisonResult {
-  kCFCompareLessThan = -1,
-  kCFCompareEqualTo = 0,
-  kCFCompareGreaterThan = 1
+// A constant exported by CoreFoundation, such as kCFNull, which dereferences to its handle.
+pub struct CFConstant<T: 'static> {
+  get: fn() -> &'static T,
+  marker: PhantomData<&'static T>
 }
 
-pub type CFComparatorFunction = unsafe extern fn(val1: *const c_void, val2: *const c_void, context: *mut c_void) -> CFComparisonResult;
+impl<T> CFConstant<T> {
+  #[doc(hidden)] pub const fn new(get: fn() -> &'static T) -> CFConstant<T> {
+    return CFConstant { get, marker: PhantomData };
+  }
+}
 
-pub const kCFNotFound: CFIndex = -1;
+impl<T> Deref for CFConstant<T> {
+  type Target = T;
 
-pub struct CFRange {
-  location: CFIndex,
-  length: CFIndex
+  fn deref(&self) -> &T {
+    return (self.get)();
+  }
+}
+
+impl<T: fmt::Debug> fmt::Debug for CFConstant<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return fmt::Debug::fmt(&**self, f);
+  }
+}
+
+impl<T: fmt::Display> fmt::Display for CFConstant<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return fmt::Display::fmt(&**self, f);
+  }
 }
 
-pub fn CFRangeMake(loc: CFIndex, len: CFIndex) -> CFRange {
-  return CFRange { location: loc, length: len };
+// Handles own nothing themselves, so a bitwise copy is what crosses the FFI boundary.
+fn raw<T: Subtype<U>, U>(cf: &T) -> U {
+  return unsafe { ptr::read(cf.upcast()) };
 }
 
-mod ext {
+pub const kCFNotFound: CFIndex = -1;
+
+#[cfg(target_vendor = "apple")]
+pub(crate) mod ext {
   use crate::*;
   
-  #[link(name = "CoreFoundation", kind = "framework")]
-  extern {
-    pub fn CFCopyTypeIDDescription(type_id: CFTypeID) -> CFStringRef;
+  cf_extern! {
+    pub fn CFCopyTypeIDDescription(type_id: CFTypeID) -> Option<CFStringRef>;
   }
 }
 
-pub fn CFCopyTypeIDDescription(type_id: CFTypeID) -> CFStringRef {
-  return unsafe { ext::CFCopyTypeIDDescription(type_id) };
+#[cfg(not(target_vendor = "apple"))]
+pub(crate) use crate::portable as ext;
+
+#[cfg(not(feature = "backend"))]
+use self::ext as ffi;
+
+#[cfg(feature = "backend")]
+use crate::backend::base as ffi;
+
+pub fn CFCopyTypeIDDescription(type_id: CFTypeID) -> Option<CFOwned<CFStringRef>> {
+  return unsafe { ffi::CFCopyTypeIDDescription(type_id).map(|description| CFOwned::from_create_rule(description)) };
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::*;
+
+  #[test]
+  fn it_bridges_booleans() {
+    let boolean: Boolean = unsafe { mem::transmute(2u8) };
+
+    assert!(bool::from(boolean));
+    assert_eq!(Boolean::TRUE, boolean);
+    assert_eq!(Boolean::FALSE, Boolean::from(false));
+  }
 }
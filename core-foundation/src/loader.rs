@@ -0,0 +1,307 @@
+// Resolves CoreFoundation at run time instead of linking against it, so that a binary still starts on a system without
+// the framework and can report why it is unavailable. Apple targets use it for every `ext` block when the `dlopen`
+// feature is enabled.
+
+use crate::*;
+
+use std::ffi::CString;
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::OnceLock;
+
+extern "C" {
+  fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+  fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+  fn dlerror() -> *const c_char;
+}
+
+const RTLD_NOW: c_int = 2;
+
+#[cfg(target_vendor = "apple")]
+pub const kCoreFoundationPath: &CStr = c"/System/Library/Frameworks/CoreFoundation.framework/CoreFoundation";
+
+#[cfg(not(target_vendor = "apple"))]
+pub const kCoreFoundationPath: &CStr = c"libCoreFoundation.so";
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum LoadError {
+  Library { path: String, message: String },
+  Symbol { path: String, name: String, message: String }
+}
+
+impl fmt::Display for LoadError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return match self {
+      LoadError::Library { path, message } => write!(f, "could not load {}: {}", path, message),
+      LoadError::Symbol { path, name, message } => write!(f, "could not find {} in {}: {}", name, path, message)
+    };
+  }
+}
+
+impl Error for LoadError { }
+
+fn last_error() -> String {
+  let message = unsafe { dlerror() };
+
+  if message.is_null() {
+    return String::from("unknown error");
+  }
+
+  return unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned();
+}
+
+// A library opened with dlopen. It is never closed, as the functions and constants taken from it live for the rest of
+// the program.
+pub struct Library {
+  handle: NonNull<c_void>,
+  path: String
+}
+
+unsafe impl Send for Library { }
+unsafe impl Sync for Library { }
+
+impl Library {
+  // The path is handed to dlopen as it is; only the copy kept for error messages is made readable.
+  pub fn open(path: &CStr) -> Result<Library, LoadError> {
+    let handle = unsafe { dlopen(path.as_ptr(), RTLD_NOW) };
+    let path = path.to_string_lossy().into_owned();
+
+    return match NonNull::new(handle) {
+      Some(handle) => Ok(Library { handle, path }),
+      None => Err(LoadError::Library { message: last_error(), path })
+    };
+  }
+
+  pub fn path(&self) -> &str {
+    return &self.path;
+  }
+
+  pub fn symbol(&self, name: &str) -> Result<NonNull<c_void>, LoadError> {
+    let error = |message| LoadError::Symbol { path: self.path.clone(), name: String::from(name), message };
+    let symbol = CString::new(name).map_err(|_| error(String::from("invalid symbol name")))?;
+
+    return NonNull::new(unsafe { dlsym(self.handle.as_ptr(), symbol.as_ptr()) }).ok_or_else(|| error(last_error()));
+  }
+}
+
+static CoreFoundationLibrary: OnceLock<Result<Library, LoadError>> = OnceLock::new();
+
+// Opens the framework on first use, or the library named by HAGANE_COREFOUNDATION_PATH when it is set. Call it before
+// anything else to find out whether CoreFoundation is available; the functions and constants bound to it panic with
+// the same error.
+pub fn CoreFoundation() -> Result<&'static Library, LoadError> {
+  let library = CoreFoundationLibrary.get_or_init(|| match std::env::var("HAGANE_COREFOUNDATION_PATH") {
+    Ok(path) => match CString::new(path.as_str()) {
+      Ok(path) => Library::open(&path),
+      Err(_) => Err(LoadError::Library { path, message: String::from("invalid path") })
+    },
+    Err(_) => Library::open(kCoreFoundationPath)
+  });
+
+  return library.as_ref().map_err(Clone::clone);
+}
+
+// The address of a function or constant, looked up on its first use.
+#[doc(hidden)] pub struct Symbol {
+  name: &'static str,
+  address: AtomicPtr<c_void>
+}
+
+impl Symbol {
+  pub const fn new(name: &'static str) -> Symbol {
+    return Symbol { name, address: AtomicPtr::new(ptr::null_mut()) };
+  }
+
+  pub fn resolve(&self, library: fn() -> Result<&'static Library, LoadError>) -> NonNull<c_void> {
+    if let Some(address) = NonNull::new(self.address.load(Ordering::Acquire)) {
+      return address;
+    }
+
+    let address = match library().and_then(|library| library.symbol(self.name)) {
+      Ok(address) => address,
+      Err(error) => panic!("{}", error)
+    };
+
+    self.address.store(address.as_ptr(), Ordering::Release);
+
+    return address;
+  }
+}
+
+// A constant of the library, read in place rather than copied, so nothing stands in for it before it is found.
+#[doc(hidden)] pub struct Constant<T: 'static> {
+  symbol: Symbol,
+  library: fn() -> Result<&'static Library, LoadError>,
+  marker: PhantomData<&'static T>
+}
+
+impl<T> Constant<T> {
+  pub const fn new(name: &'static str, library: fn() -> Result<&'static Library, LoadError>) -> Constant<T> {
+    return Constant { symbol: Symbol::new(name), library, marker: PhantomData };
+  }
+
+  pub fn get(&self) -> &'static T {
+    return unsafe { &*(self.symbol.resolve(self.library).as_ptr() as *const T) };
+  }
+}
+
+// Declares the functions and handle constants of an `ext` block, in any order, as symbols of `$library`. Both are
+// looked up on first use, and panic with the LoadError when they cannot be found. Each constant moves the rest of the
+// block into another expansion, whose depth the recursion limit caps.
+#[cfg_attr(not(target_vendor = "apple"), allow(unused_macros))]
+macro_rules! dynamic_extern {
+  ($library:path; $(pub fn $name:ident($($argument:ident: $type:ty),* $(,)?) $(-> $result:ty)?;)* pub static $constant:ident: $constant_type:ty; $($rest:tt)*) => {
+    $crate::loader::dynamic_extern! { $library; $(pub fn $name($($argument: $type),*) $(-> $result)?;)* }
+
+    pub static $constant: $crate::loader::Constant<$constant_type> = $crate::loader::Constant::new(stringify!($constant), $library);
+
+    $crate::loader::dynamic_extern! { $library; $($rest)* }
+  };
+
+  ($library:path; $(pub fn $name:ident($($argument:ident: $type:ty),* $(,)?) $(-> $result:ty)?;)*) => {
+    $(
+      pub unsafe fn $name($($argument: $type),*) $(-> $result)? {
+        static SYMBOL: $crate::loader::Symbol = $crate::loader::Symbol::new(stringify!($name));
+
+        let function: unsafe extern "C" fn($($type),*) $(-> $result)? = ::std::mem::transmute(SYMBOL.resolve($library));
+
+        return function($($argument),*);
+      }
+    )*
+  };
+}
+
+#[cfg_attr(not(target_vendor = "apple"), allow(unused_imports))]
+pub(crate) use dynamic_extern;
+
+#[cfg(test)]
+mod tests {
+  use crate::loader::{Library, LoadError};
+
+  use std::ffi::{CString, OsStr};
+  use std::os::unix::ffi::OsStrExt;
+  use std::panic;
+  use std::path::PathBuf;
+  use std::process::Command;
+  use std::sync::OnceLock;
+
+  const kStubSource: &str = "int stub_add(int a, int b) { return a + b; }\nconst void *stub_constant = (const void *) 0x10;\n";
+
+  // Removes the build directory once the library is open; the mapping outlives the file.
+  struct TemporaryDirectory(PathBuf);
+
+  impl Drop for TemporaryDirectory {
+    fn drop(&mut self) {
+      let _ = std::fs::remove_dir_all(&self.0);
+    }
+  }
+
+  // Builds a stand-in for the framework with the system C compiler, which these tests need. Its directory name is not
+  // UTF-8, so the path only opens if it reaches dlopen unchanged.
+  fn stub() -> Result<&'static Library, LoadError> {
+    static Stub: OnceLock<Library> = OnceLock::new();
+
+    return Ok(Stub.get_or_init(|| {
+      let name = [format!("hagane-loader-{}-", std::process::id()).as_bytes(), b"\xff"].concat();
+      let directory = TemporaryDirectory(std::env::temp_dir().join(OsStr::from_bytes(&name)));
+      let source = directory.0.join("stub.c");
+      let library = directory.0.join("libstub.so");
+
+      std::fs::create_dir_all(&directory.0).unwrap();
+      std::fs::write(&source, kStubSource).unwrap();
+
+      let status = Command::new("cc").arg("-shared").arg("-fPIC").arg("-o").arg(&library).arg(&source).status().expect("cc is needed to build the stub library");
+
+      assert!(status.success());
+
+      return Library::open(&CString::new(library.as_os_str().as_bytes()).unwrap()).unwrap();
+    }));
+  }
+
+  mod stub {
+    use crate::*;
+
+    use std::os::raw::c_int;
+
+    crate::loader::dynamic_extern! {
+      super::stub;
+
+      pub fn stub_add(a: c_int, b: c_int) -> c_int;
+      pub static stub_constant: CFStringRef;
+      pub fn stub_missing();
+      pub static stub_absent: CFStringRef;
+    }
+  }
+
+  // The block the generator writes for CFString.h, with stand-ins for the types it names that are not bound yet.
+  #[allow(dead_code)]
+  mod generated {
+    use crate::*;
+
+    use std::os::raw::c_ulong;
+
+    type CFLocaleRef = CFTypeRef;
+    type CFStringCompareFlags = CFOptionFlags;
+
+    macro_rules! cf_extern {
+      ($($item:tt)*) => {
+        crate::loader::dynamic_extern! {
+          super::stub;
+
+          $($item)*
+        }
+      };
+    }
+
+    include!("../../generator/fixtures/string_extern.rs");
+  }
+
+  fn message(panic: Box<dyn std::any::Any + Send>) -> String {
+    return panic.downcast::<String>().map(|message| *message).unwrap_or_default();
+  }
+
+  #[test]
+  fn it_reports_missing_libraries() {
+    let error = Library::open(c"libhagane-missing.so").err().unwrap();
+
+    assert!(matches!(&error, LoadError::Library { path, .. } if path == "libhagane-missing.so"));
+    assert!(error.to_string().starts_with("could not load libhagane-missing.so: "));
+  }
+
+  #[test]
+  fn it_resolves_symbols() {
+    unsafe {
+      assert_eq!(5, stub::stub_add(2, 3));
+    }
+
+    assert_eq!(0x10, stub::stub_constant.get().0.as_ptr() as usize);
+  }
+
+  #[test]
+  fn it_reports_missing_symbols() {
+    let error = stub().unwrap().symbol("stub_missing").err().unwrap();
+    let panic = panic::catch_unwind(|| unsafe { stub::stub_missing() }).unwrap_err();
+
+    assert!(matches!(&error, LoadError::Symbol { name, .. } if name == "stub_missing"));
+    assert_eq!(error.to_string(), message(panic));
+  }
+
+  #[test]
+  fn it_reports_missing_constants() {
+    let error = stub().unwrap().symbol("stub_absent").err().unwrap();
+    let panic = panic::catch_unwind(|| stub::stub_absent.get()).unwrap_err();
+
+    assert_eq!(error.to_string(), message(panic));
+  }
+
+  #[test]
+  fn it_binds_generated_declarations() {
+    let panic = panic::catch_unwind(|| unsafe { generated::CFStringGetTypeID() }).unwrap_err();
+
+    assert!(message(panic).starts_with("could not find CFStringGetTypeID in "));
+
+    let panic = panic::catch_unwind(|| generated::kCFStringTransformToLatin.get()).unwrap_err();
+
+    assert!(message(panic).starts_with("could not find kCFStringTransformToLatin in "));
+  }
+}
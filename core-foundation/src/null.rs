@@ -1,35 +1,34 @@
 use crate::*;
 
-mod ext {
+#[cfg(target_vendor = "apple")]
+pub(crate) mod ext {
   use crate::*;
 
-  extern {
+  cf_extern! {
     pub fn CFNullGetTypeID() -> CFTypeID;
 
     pub static kCFNull: CFNullRef;
   }
 }
 
-#[repr(transparent)] pub struct CFNullRef(usize);
+#[cfg(not(target_vendor = "apple"))]
+pub(crate) use crate::portable::null as ext;
 
-unsafe impl Subtype<CFTypeRef> for CFNullRef {
-  unsafe fn upcast(&self) -> CFTypeRef {
-    return CFTypeRef(self.0);
-  }
-}
+#[cfg(not(feature = "backend"))]
+use self::ext as ffi;
 
-unsafe impl Subtype<CFNullRef> for CFNullRef {
-  unsafe fn upcast(&self) -> CFNullRef {
-    return CFNullRef(self.0);
-  }
-}
+#[cfg(feature = "backend")]
+use crate::backend::null as ffi;
 
+cf_class! {
+  pub unsafe class CFNullRef: CFTypeRef = CFNullGetTypeID where Send, Sync;
+}
 
 pub fn CFNullGetTypeID() -> CFTypeID {
-  return unsafe { ext::CFNullGetTypeID() };
+  return unsafe { ffi::CFNullGetTypeID() };
 }
 
-pub static kCFNull: &'static CFNullRef = unsafe { &ext::kCFNull };
+pub static kCFNull: CFConstant<CFNullRef> = cf_constant!(ext::kCFNull);
 
 #[cfg(test)]
 mod tests {
@@ -37,7 +36,7 @@ mod tests {
 
   #[test]
   fn it_nulls() {
-    assert_eq!(CFNullGetTypeID(), CFGetTypeID(kCFNull));
+    assert_eq!(CFNullGetTypeID(), CFGetTypeID(&*kCFNull));
     assert_eq!(CFNullGetTypeID(), kCFNull.get_type_id());
   }
 }
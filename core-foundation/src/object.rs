@@ -1,9 +1,10 @@
 use crate::*;
 
-mod ext {
+#[cfg(target_vendor = "apple")]
+pub(crate) mod ext {
   use crate::*;
 
-  extern {
+  cf_extern! {
     pub fn CFGetTypeID(cf: CFTypeRef) -> CFTypeID;
     pub fn CFRetain(cf: CFTypeRef) -> CFTypeRef;
     pub fn CFRelease(cf: CFTypeRef);
@@ -11,63 +12,288 @@ mod ext {
     pub fn CFGetRetainCount(cf: CFTypeRef) -> CFIndex;
     pub fn CFEqual(cf1: CFTypeRef, cf2: CFTypeRef) -> Boolean;
     pub fn CFHash(cf: CFTypeRef) -> CFHashCode;
-    pub fn CFCopyDescription(cf: CFTypeRef) -> CFStringRef;
+    pub fn CFCopyDescription(cf: CFTypeRef) -> Option<CFStringRef>;
     pub fn CFGetAllocator(cf: CFTypeRef) -> CFAllocatorRef;
 
     pub fn CFShow(obj: CFTypeRef);
   }
 }
 
-#[repr(transparent)] pub struct CFTypeRef(crate usize);
+#[cfg(not(target_vendor = "apple"))]
+pub(crate) use crate::portable::object as ext;
 
-unsafe impl Subtype<CFTypeRef> for CFTypeRef {
-  unsafe fn upcast(&self) -> CFTypeRef {
-    return CFTypeRef(self.0);
+#[cfg(not(feature = "backend"))]
+use self::ext as ffi;
+
+#[cfg(feature = "backend")]
+use crate::backend::object as ffi;
+
+cf_class! {
+  pub unsafe class CFTypeRef;
+}
+
+// An owned reference, as returned by Create and Copy functions, released when dropped.
+pub struct CFOwned<T: Subtype<CFTypeRef>>(T);
+
+impl<T: Subtype<CFTypeRef>> CFOwned<T> {
+  pub unsafe fn from_create_rule(cf: T) -> CFOwned<T> {
+    #[cfg(feature = "leak-tracker")]
+    tracker::record(tracker::Event::Create, cf.upcast());
+
+    return CFOwned(cf);
+  }
+
+  pub fn into_raw(self) -> T {
+    #[cfg(feature = "leak-tracker")]
+    tracker::record(tracker::Event::IntoRaw, self.0.upcast());
+
+    return self.leak();
+  }
+
+  // Gives up ownership without releasing, or recording the reference as given up.
+  fn leak(self) -> T {
+    let cf = unsafe { ptr::read(&self.0) };
+
+    mem::forget(self);
+
+    return cf;
+  }
+}
+
+impl<T: Subtype<CFTypeRef>> Deref for CFOwned<T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    return &self.0;
+  }
+}
+
+impl<T: Subtype<CFTypeRef>> Drop for CFOwned<T> {
+  fn drop(&mut self) {
+    #[cfg(feature = "leak-tracker")]
+    tracker::record(tracker::Event::Release, self.0.upcast());
+
+    unsafe { ffi::CFRelease(raw(&self.0)) };
+  }
+}
+
+impl<T: Subtype<CFTypeRef>> Clone for CFOwned<T> {
+  fn clone(&self) -> CFOwned<T> {
+    return CFRetain(&self.0);
+  }
+}
+
+impl<T: Subtype<CFTypeRef> + fmt::Debug> fmt::Debug for CFOwned<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return self.0.fmt(f);
+  }
+}
+
+impl<T: Subtype<CFTypeRef> + fmt::Display> fmt::Display for CFOwned<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return self.0.fmt(f);
+  }
+}
+
+impl<T: Subtype<CFTypeRef> + PartialEq> PartialEq for CFOwned<T> {
+  fn eq(&self, other: &CFOwned<T>) -> bool {
+    return self.0 == other.0;
+  }
+}
+
+impl<T: Subtype<CFTypeRef> + Eq> Eq for CFOwned<T> { }
+
+impl<T: Subtype<CFTypeRef> + Hash> Hash for CFOwned<T> {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.0.hash(state);
+  }
+}
+
+// A reference borrowed from its parent, as returned by Get functions, valid for as long as the parent is.
+pub struct CFRef<'a, T: Subtype<CFTypeRef>>(T, PhantomData<&'a T>);
+
+impl<'a, T: Subtype<CFTypeRef>> CFRef<'a, T> {
+  pub unsafe fn from_get_rule(cf: T) -> CFRef<'a, T> {
+    return CFRef(cf, PhantomData);
+  }
+
+  pub fn upcast<U: Subtype<CFTypeRef>>(&self) -> CFRef<'a, U> where T: Subtype<U> {
+    return unsafe { CFRef::from_get_rule(raw(&self.0)) };
+  }
+}
+
+impl<'a, T: Subtype<CFTypeRef>> Deref for CFRef<'a, T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    return &self.0;
+  }
+}
+
+impl<'a, T: Subtype<CFTypeRef>> Clone for CFRef<'a, T> {
+  fn clone(&self) -> CFRef<'a, T> {
+    return unsafe { CFRef::from_get_rule(ptr::read(&self.0)) };
+  }
+}
+
+impl<'a, T: Subtype<CFTypeRef> + fmt::Debug> fmt::Debug for CFRef<'a, T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return self.0.fmt(f);
+  }
+}
+
+impl<'a, T: Subtype<CFTypeRef> + fmt::Display> fmt::Display for CFRef<'a, T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return self.0.fmt(f);
+  }
+}
+
+impl<'a, T: Subtype<CFTypeRef> + PartialEq> PartialEq for CFRef<'a, T> {
+  fn eq(&self, other: &CFRef<'a, T>) -> bool {
+    return self.0 == other.0;
+  }
+}
+
+impl<'a, T: Subtype<CFTypeRef> + Eq> Eq for CFRef<'a, T> { }
+
+impl<'a, T: Subtype<CFTypeRef> + Hash> Hash for CFRef<'a, T> {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.0.hash(state);
+  }
+}
+
+// Implemented by handle types whose objects can be recognised by type ID alone.
+pub unsafe trait CFClass : Subtype<CFTypeRef> {
+  fn type_id() -> CFTypeID;
+}
+
+#[derive(Debug)]
+pub struct CFDowncastError {
+  pub expected: CFTypeID,
+  pub actual: CFTypeID,
+  pub description: String
+}
+
+impl fmt::Display for CFDowncastError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return match CFCopyTypeIDDescription(self.expected) {
+      Some(expected) => write!(f, "expected an object of type {}, found {}", String::from(&*expected), self.description),
+      None => write!(f, "expected an object of type ID {}, found {}", self.expected.0, self.description)
+    };
+  }
+}
+
+impl Error for CFDowncastError { }
+
+// A failed downcast of an owned object, which gives the object back rather than releasing it.
+pub struct CFOwnedDowncastError<T: Subtype<CFTypeRef>> {
+  pub object: CFOwned<T>,
+  pub error: CFDowncastError
+}
+
+impl<T: Subtype<CFTypeRef> + fmt::Debug> fmt::Debug for CFOwnedDowncastError<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return f.debug_struct("CFOwnedDowncastError").field("object", &self.object).field("error", &self.error).finish();
+  }
+}
+
+impl<T: Subtype<CFTypeRef>> fmt::Display for CFOwnedDowncastError<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return fmt::Display::fmt(&self.error, f);
+  }
+}
+
+impl<T: Subtype<CFTypeRef> + fmt::Debug> Error for CFOwnedDowncastError<T> {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    return Some(&self.error);
+  }
+}
+
+impl<T: Subtype<CFTypeRef>> CFOwned<T> {
+  pub fn downcast<U: CFClass>(self) -> Result<CFOwned<U>, CFOwnedDowncastError<T>> {
+    let cf = match self.0.downcast::<U>() {
+      Ok(cf) => unsafe { ptr::read(cf) },
+      Err(error) => return Err(CFOwnedDowncastError { object: self, error })
+    };
+
+    mem::forget(self);
+
+    return Ok(CFOwned(cf));
+  }
+}
+
+impl<'a, T: Subtype<CFTypeRef>> CFRef<'a, T> {
+  pub fn downcast<U: CFClass>(self) -> Result<CFRef<'a, U>, CFDowncastError> {
+    return Ok(unsafe { CFRef::from_get_rule(ptr::read(self.0.downcast::<U>()?)) });
+  }
+}
+
+impl<T: CFClass> TryFrom<CFOwned<CFTypeRef>> for CFOwned<T> {
+  type Error = CFOwnedDowncastError<CFTypeRef>;
+
+  fn try_from(cf: CFOwned<CFTypeRef>) -> Result<CFOwned<T>, CFOwnedDowncastError<CFTypeRef>> {
+    return cf.downcast();
+  }
+}
+
+impl<'a, T: CFClass> TryFrom<CFRef<'a, CFTypeRef>> for CFRef<'a, T> {
+  type Error = CFDowncastError;
+
+  fn try_from(cf: CFRef<'a, CFTypeRef>) -> Result<CFRef<'a, T>, CFDowncastError> {
+    return cf.downcast();
   }
 }
 
 pub fn CFGetTypeID<T: Subtype<CFTypeRef>>(cf: &T) -> CFTypeID {
-  return unsafe { ext::CFGetTypeID(cf.upcast()) };
+  return unsafe { ffi::CFGetTypeID(raw(cf)) };
 }
 
-pub unsafe fn CFRetain<T: Subtype<CFTypeRef>>(cf: T) -> T {
-  mem::forget(ext::CFRetain(cf.upcast()));
+pub fn CFRetain<T: Subtype<CFTypeRef>>(cf: &T) -> CFOwned<T> {
+  unsafe { ffi::CFRetain(raw(cf)) };
 
-  return cf;
+  #[cfg(feature = "leak-tracker")]
+  tracker::record(tracker::Event::Retain, cf.upcast());
+
+  return CFOwned(unsafe { ptr::read(cf) });
 }
 
-pub fn CFRelease<T: Subtype<CFTypeRef>>(cf: T) {
-  unsafe { ext::CFRelease(cf.upcast()) };
+pub fn CFRelease<T: Subtype<CFTypeRef>>(cf: CFOwned<T>) {
+  mem::drop(cf);
 }
 
-pub unsafe fn CFAutorelease<T: Subtype<CFTypeRef>>(cf: T) -> T {
-  mem::forget(ext::CFAutorelease(cf.upcast()));
+pub unsafe fn CFAutorelease<T: Subtype<CFTypeRef>>(cf: CFOwned<T>) -> T {
+  #[cfg(feature = "leak-tracker")]
+  tracker::record(tracker::Event::Autorelease, cf.upcast());
+
+  let cf = cf.leak();
+
+  ffi::CFAutorelease(raw(&cf));
 
   return cf;
 }
 
 pub fn CFGetRetainCount<T: Subtype<CFTypeRef>>(cf: &T) -> CFIndex {
-  return unsafe { ext::CFGetRetainCount(cf.upcast()) };
+  return unsafe { ffi::CFGetRetainCount(raw(cf)) };
 }
 
-pub fn CFEqual<T1: Subtype<CFTypeRef>, T2: Subtype<CFTypeRef>>(cf1: &T1, cf2: &T2) -> Boolean {
-  return unsafe { ext::CFEqual(cf1.upcast(), cf2.upcast()) };
+pub fn CFEqual<T1: Subtype<CFTypeRef>, T2: Subtype<CFTypeRef>>(cf1: &T1, cf2: &T2) -> bool {
+  return unsafe { ffi::CFEqual(raw(cf1), raw(cf2)) }.into();
 }
 
 pub fn CFHash<T: Subtype<CFTypeRef>>(cf: &T) -> CFHashCode {
-  return unsafe { ext::CFHash(cf.upcast()) };
+  return unsafe { ffi::CFHash(raw(cf)) };
 }
 
-pub fn CFCopyDescription<T: Subtype<CFTypeRef>>(cf: &T) -> CFStringRef {
-  return unsafe { ext::CFCopyDescription(cf.upcast()) };
+pub fn CFCopyDescription<T: Subtype<CFTypeRef>>(cf: &T) -> Option<CFOwned<CFStringRef>> {
+  return unsafe { ffi::CFCopyDescription(raw(cf)).map(|description| CFOwned::from_create_rule(description)) };
 }
 
-pub fn CFGetAllocator<T: Subtype<CFTypeRef>>(cf: &T) -> CFAllocatorRef {
-  return unsafe { ext::CFGetAllocator(cf.upcast()).retain() };
+pub fn CFGetAllocator<T: Subtype<CFTypeRef>>(cf: &T) -> CFRef<'_, CFAllocatorRef> {
+  return unsafe { CFRef::from_get_rule(ffi::CFGetAllocator(raw(cf))) };
 }
 
 pub fn CFShow<T: Subtype<CFTypeRef>>(cf: &T) {
-  unsafe { ext::CFShow(cf.upcast()) };
+  unsafe { ffi::CFShow(raw(cf)) };
 }
 
 pub trait CFTypeClass : Subtype<CFTypeRef> {
@@ -75,41 +301,63 @@ pub trait CFTypeClass : Subtype<CFTypeRef> {
     return CFGetTypeID(self);
   }
 
-  unsafe fn retain(self) -> Self {
+  fn retain(&self) -> CFOwned<Self> {
     return CFRetain(self);
   }
 
-  fn release(self) {
-    CFRelease(self);
-  }
-
-  unsafe fn autorelease(self) -> Self {
-    return CFAutorelease(self);
-  }
-
   fn get_retain_count(&self) -> CFIndex {
     return CFGetRetainCount(self);
   }
 
   fn equal<T: Subtype<CFTypeRef>>(&self, other: &T) -> bool {
-    return CFEqual(self, other) == Boolean::TRUE;
+    return CFEqual(self, other);
   }
 
   fn hash(&self) -> CFHashCode {
     return CFHash(self);
   }
 
-  fn copy_description(&self) -> CFStringRef {
+  fn copy_description(&self) -> Option<CFOwned<CFStringRef>> {
     return CFCopyDescription(self);
   }
 
-  fn get_allocator(&self) -> CFAllocatorRef {
+  fn get_allocator(&self) -> CFRef<'_, CFAllocatorRef> {
     return CFGetAllocator(self);
   }
 
   fn show(&self) {
     CFShow(self);
   }
+
+  fn fmt_debug(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return match self.copy_description() {
+      Some(description) => f.write_str(&String::from(&*description)),
+      None => write!(f, "<{} {:p}>", self.get_type_id().0, Subtype::<CFTypeRef>::upcast(self).0)
+    };
+  }
+
+  // Strings display as their contents, as with the %@ format specifier, and anything else as its description.
+  fn fmt_display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.get_type_id() != CFStringGetTypeID() {
+      return self.fmt_debug(f);
+    }
+
+    return f.write_str(&String::from(unsafe { &*(self as *const Self as *const CFStringRef) }));
+  }
+
+  fn downcast<T: CFClass>(&self) -> Result<&T, CFDowncastError> {
+    let actual = CFGetTypeID(self);
+
+    if actual != T::type_id() {
+      return Err(CFDowncastError {
+        expected: T::type_id(),
+        actual,
+        description: CFCopyTypeIDDescription(actual).map_or_else(|| format!("type ID {}", actual.0), |description| String::from(&*description))
+      });
+    }
+
+    return Ok(unsafe { &*(self as *const Self as *const T) });
+  }
 }
 
 impl<T> CFTypeClass for T where T: Subtype<CFTypeRef> { }
@@ -118,9 +366,83 @@ impl<T> CFTypeClass for T where T: Subtype<CFTypeRef> { }
 mod tests {
   use crate::*;
 
+  use std::collections::HashSet;
+
   #[test]
   fn it_compares() {
-    assert_eq!(CFEqual(kCFNull, kCFAllocatorSystemDefault), Boolean::FALSE);
+    assert!(!CFEqual(&*kCFNull, &*kCFAllocatorSystemDefault));
+  }
+
+  #[test]
+  fn it_retains() {
+    let description = CFCopyDescription(&*kCFNull).unwrap();
+
+    assert_eq!(CFStringGetTypeID(), description.get_type_id());
+    assert_eq!(1, description.get_retain_count());
+
+    let copy = description.clone();
+
+    assert_eq!(2, description.get_retain_count());
+    mem::drop(copy);
+    assert_eq!(1, description.get_retain_count());
+  }
+
+  #[test]
+  fn it_borrows() {
+    let description = CFCopyDescription(&*kCFNull).unwrap();
+    let allocator = description.get_allocator();
+    let object: CFRef<CFTypeRef> = allocator.upcast();
+
+    assert!(object.equal(&*CFAllocatorGetDefault()));
+    assert_eq!(1, description.get_retain_count());
+  }
+
+  #[test]
+  fn it_formats() {
+    let description = CFCopyDescription(&*kCFNull).unwrap();
+
+    assert_eq!(String::from(&*description), description.to_string());
+    assert!(format!("{:?}", description).starts_with("<CFString"));
+  }
+
+  #[test]
+  fn it_hashes() {
+    let mut strings = HashSet::new();
+
+    strings.insert(CFCopyDescription(&*kCFNull).unwrap());
+    strings.insert(CFCopyDescription(&*kCFNull).unwrap());
+
+    assert_eq!(1, strings.len());
+    assert_eq!(CFHash(&**strings.iter().next().unwrap()), CFHash(&*CFCopyDescription(&*kCFNull).unwrap()));
+  }
+
+  #[test]
+  fn it_shares_immutable_objects() {
+    fn assert_send_sync<T: Send + Sync>() { }
+
+    assert_send_sync::<CFOwned<CFStringRef>>();
+    assert_send_sync::<CFRef<'static, CFAllocatorRef>>();
+    assert_send_sync::<&'static CFNullRef>();
+  }
+
+  #[test]
+  fn it_downcasts() {
+    let description = CFCopyDescription(&*kCFNull).unwrap();
+    let object: &CFTypeRef = description.upcast();
+
+    assert!(object.downcast::<CFStringRef>().is_ok());
+    assert_eq!("CFString", object.downcast::<CFNullRef>().err().unwrap().description);
+  }
+
+  #[test]
+  fn it_returns_objects_that_fail_to_downcast() {
+    let description = CFCopyDescription(&*kCFNull).unwrap();
+    let error = description.clone().downcast::<CFNullRef>().err().unwrap();
+
+    assert_eq!("CFString", error.error.description);
+    assert!(CFEqual(&*description, &*error.object));
+    assert_eq!(2, description.get_retain_count());
+    assert!(error.object.downcast::<CFStringRef>().is_ok());
   }
 }
 
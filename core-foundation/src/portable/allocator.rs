@@ -0,0 +1,341 @@
+use crate::*;
+
+use std::alloc::{self, Layout};
+use std::cell::Cell;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::AtomicIsize;
+
+use crate::portable::runtime::{self, CFRuntimeBase, Constant, _kCFRuntimeIDCFAllocator};
+use crate::portable::string;
+
+#[repr(C)] pub struct CFAllocator {
+  pub base: CFRuntimeBase,
+  pub context: CFAllocatorContext
+}
+
+pub static CFAllocatorClass: Constant<CFRuntimeClass> = Constant(CFRuntimeClass {
+  finalize: Some(finalize),
+  copyDebugDesc: Some(copy_debug_description),
+  ..CFRuntimeClass::new(c"CFAllocator")
+});
+
+// Blocks handed out by the system allocator are prefixed with their size, padded to keep this alignment.
+const kCFAllocatorAlignment: usize = 16;
+
+const fn builtin(name: &'static CStr, allocate: CFAllocatorAllocateCallBack, reallocate: CFAllocatorReallocateCallBack, deallocate: CFAllocatorDeallocateCallBack) -> CFAllocator {
+  return CFAllocator {
+    base: CFRuntimeBase::constant(_kCFRuntimeIDCFAllocator),
+    context: CFAllocatorContext {
+      version: 0,
+      info: name.as_ptr() as *mut c_void,
+      retain: Some(builtin_retain),
+      release: Some(builtin_release),
+      copyDescription: Some(builtin_copy_description),
+      allocate: Some(allocate),
+      reallocate: Some(reallocate),
+      deallocate: Some(deallocate),
+      preferredSize: Some(system_preferred_size)
+    }
+  };
+}
+
+static SystemDefault: Constant<CFAllocator> = Constant(builtin(c"kCFAllocatorSystemDefault", system_allocate, system_reallocate, system_deallocate));
+static Malloc: Constant<CFAllocator> = Constant(builtin(c"kCFAllocatorMalloc", system_allocate, system_reallocate, system_deallocate));
+static MallocZone: Constant<CFAllocator> = Constant(builtin(c"kCFAllocatorMallocZone", system_allocate, system_reallocate, system_deallocate));
+static Null: Constant<CFAllocator> = Constant(builtin(c"kCFAllocatorNull", null_allocate, null_reallocate, null_deallocate));
+static UseContext: Constant<CFAllocator> = Constant(builtin(c"kCFAllocatorUseContext", null_allocate, null_reallocate, null_deallocate));
+
+const fn constant(allocator: &'static Constant<CFAllocator>) -> CFAllocatorRef {
+  return CFAllocatorRef(unsafe { NonNull::new_unchecked(allocator as *const Constant<CFAllocator> as *mut c_void) });
+}
+
+pub static kCFAllocatorSystemDefault: CFAllocatorRef = constant(&SystemDefault);
+pub static kCFAllocatorMalloc: CFAllocatorRef = constant(&Malloc);
+pub static kCFAllocatorMallocZone: CFAllocatorRef = constant(&MallocZone);
+pub static kCFAllocatorNull: CFAllocatorRef = constant(&Null);
+pub static kCFAllocatorUseContext: CFAllocatorRef = constant(&UseContext);
+
+thread_local! {
+  static Default: Cell<*const c_void> = const { Cell::new(ptr::null()) };
+}
+
+unsafe extern "C" fn builtin_retain(info: *const c_void) -> *const c_void {
+  return info;
+}
+
+unsafe extern "C" fn builtin_release(_info: *const c_void) { }
+
+unsafe extern "C" fn builtin_copy_description(info: *const c_void) -> Option<CFStringRef> {
+  return string::create(ptr::null(), &CStr::from_ptr(info as *const c_char).to_string_lossy());
+}
+
+// None for negative sizes and ones too large to allocate with the header.
+fn system_layout(size: CFIndex) -> Option<Layout> {
+  let size = usize::try_from(size).ok()?.checked_add(kCFAllocatorAlignment)?;
+
+  return Layout::from_size_align(size, kCFAllocatorAlignment).ok();
+}
+
+unsafe extern "C" fn system_allocate(allocSize: CFIndex, _hint: CFOptionFlags, _info: *const c_void) -> *mut c_void {
+  let layout = match system_layout(allocSize) {
+    Some(layout) => layout,
+    None => return ptr::null_mut()
+  };
+  let block = alloc::alloc(layout);
+
+  if block.is_null() {
+    return ptr::null_mut();
+  }
+
+  *(block as *mut usize) = allocSize as usize;
+
+  return block.add(kCFAllocatorAlignment) as *mut c_void;
+}
+
+unsafe extern "C" fn system_reallocate(ptr: *mut c_void, newsize: CFIndex, hint: CFOptionFlags, info: *mut c_void) -> *mut c_void {
+  if ptr.is_null() {
+    return system_allocate(newsize, hint, info);
+  }
+
+  let new_layout = match system_layout(newsize) {
+    Some(layout) => layout,
+    None => return ptr::null_mut()
+  };
+  let block = (ptr as *mut u8).sub(kCFAllocatorAlignment);
+  let layout = Layout::from_size_align_unchecked(*(block as *const usize) + kCFAllocatorAlignment, kCFAllocatorAlignment);
+  let block = alloc::realloc(block, layout, new_layout.size());
+
+  if block.is_null() {
+    return ptr::null_mut();
+  }
+
+  *(block as *mut usize) = newsize as usize;
+
+  return block.add(kCFAllocatorAlignment) as *mut c_void;
+}
+
+unsafe extern "C" fn system_deallocate(ptr: *mut c_void, _info: *const c_void) {
+  let block = (ptr as *mut u8).sub(kCFAllocatorAlignment);
+  let layout = Layout::from_size_align_unchecked(*(block as *const usize) + kCFAllocatorAlignment, kCFAllocatorAlignment);
+
+  alloc::dealloc(block, layout);
+}
+
+// Sizes that cannot be rounded up, negative or close to CFIndex::MAX, come back unchanged.
+unsafe extern "C" fn system_preferred_size(size: CFIndex, _hint: CFOptionFlags, _info: *const c_void) -> CFIndex {
+  let alignment = kCFAllocatorAlignment as CFIndex;
+
+  if size < 0 {
+    return size;
+  }
+
+  return size.checked_add(alignment - 1).map_or(size, |size| size / alignment * alignment);
+}
+
+unsafe extern "C" fn null_allocate(_allocSize: CFIndex, _hint: CFOptionFlags, _info: *const c_void) -> *mut c_void {
+  return ptr::null_mut();
+}
+
+unsafe extern "C" fn null_reallocate(_ptr: *mut c_void, _newsize: CFIndex, _hint: CFOptionFlags, _info: *mut c_void) -> *mut c_void {
+  return ptr::null_mut();
+}
+
+unsafe extern "C" fn null_deallocate(_ptr: *mut c_void, _info: *const c_void) { }
+
+unsafe extern "C" fn finalize(cf: CFTypeRef) {
+  let allocator = &*(cf.0.as_ptr() as *const CFAllocator);
+  let context = allocator.context;
+
+  if allocator.base.allocator == kCFAllocatorUseContext.0.as_ptr() {
+    context_deallocate(&context, cf.0.as_ptr());
+  }
+
+  context_release(&context, context.info);
+}
+
+unsafe extern "C" fn copy_debug_description(cf: CFTypeRef) -> Option<CFStringRef> {
+  let description = format!("<CFAllocator {:p} [{:p}]>{{info = {:p}}}", cf.0, runtime::allocator_of(cf.0.as_ptr()), context(cf.0.as_ptr()).info);
+
+  return string::create(ptr::null(), &description);
+}
+
+unsafe fn context<'a>(allocator: *const c_void) -> &'a CFAllocatorContext {
+  return &(*(allocator as *const CFAllocator)).context;
+}
+
+// Callbacks left NULL are skipped, as in CoreFoundation: allocating fails, sizes are kept and info is not counted.
+unsafe fn context_retain(context: &CFAllocatorContext, info: *mut c_void) -> *mut c_void {
+  return match context.retain {
+    Some(retain) => retain(info) as *mut c_void,
+    None => info
+  };
+}
+
+unsafe fn context_release(context: &CFAllocatorContext, info: *mut c_void) {
+  if let Some(release) = context.release {
+    release(info);
+  }
+}
+
+unsafe fn context_allocate(context: &CFAllocatorContext, size: CFIndex, hint: CFOptionFlags) -> *mut c_void {
+  return match context.allocate {
+    Some(allocate) => allocate(size, hint, context.info),
+    None => ptr::null_mut()
+  };
+}
+
+unsafe fn context_reallocate(context: &CFAllocatorContext, ptr: *mut c_void, newsize: CFIndex, hint: CFOptionFlags) -> *mut c_void {
+  return match context.reallocate {
+    Some(reallocate) => reallocate(ptr, newsize, hint, context.info),
+    None => ptr::null_mut()
+  };
+}
+
+unsafe fn context_deallocate(context: &CFAllocatorContext, ptr: *mut c_void) {
+  if let Some(deallocate) = context.deallocate {
+    deallocate(ptr, context.info);
+  }
+}
+
+pub fn pointer(allocator: Option<CFAllocatorRef>) -> *const c_void {
+  return allocator.map_or(ptr::null(), |allocator| allocator.0.as_ptr());
+}
+
+// Maps kCFAllocatorDefault to the current thread's default allocator.
+pub unsafe fn resolve(allocator: *const c_void) -> *const c_void {
+  if !allocator.is_null() {
+    return allocator;
+  }
+
+  let default = Default.try_with(Cell::get).unwrap_or(ptr::null());
+
+  if default.is_null() {
+    return kCFAllocatorSystemDefault.0.as_ptr();
+  }
+
+  return default;
+}
+
+pub unsafe fn allocate(allocator: *const c_void, size: usize) -> *mut c_void {
+  if size == 0 {
+    return ptr::null_mut();
+  }
+
+  let context = context(resolve(allocator));
+
+  return context_allocate(context, size as CFIndex, CFOptionFlags(0));
+}
+
+pub unsafe fn deallocate(allocator: *const c_void, ptr: *mut c_void) {
+  if ptr.is_null() {
+    return;
+  }
+
+  context_deallocate(context(resolve(allocator)), ptr);
+}
+
+pub unsafe fn CFAllocatorGetTypeID() -> CFTypeID {
+  return _kCFRuntimeIDCFAllocator;
+}
+
+// Like CoreFoundation, the replaced default is released and the new one retained twice: once while it is the default,
+// and once more so that it outlives anything still using it.
+pub unsafe fn CFAllocatorSetDefault(allocator: CFAllocatorRef) {
+  let allocator: *const c_void = allocator.0.as_ptr();
+  let current = resolve(ptr::null());
+
+  if allocator == current {
+    return;
+  }
+
+  runtime::retain(allocator);
+  Default.with(|default| default.set(runtime::retain(allocator)));
+  runtime::release(current);
+}
+
+pub unsafe fn CFAllocatorGetDefault() -> CFAllocatorRef {
+  return CFAllocatorRef(runtime::object(resolve(ptr::null())));
+}
+
+pub unsafe fn CFAllocatorCreate(allocator: Option<CFAllocatorRef>, context: *mut CFAllocatorContext) -> Option<CFAllocatorRef> {
+  let allocator = pointer(allocator);
+  let context = &*context;
+  let info = context_retain(context, context.info);
+  let extra = mem::size_of::<CFAllocator>() - mem::size_of::<CFRuntimeBase>();
+  let instance = if allocator == kCFAllocatorUseContext.0.as_ptr() {
+    let instance = context_allocate(&CFAllocatorContext { info, ..*context }, mem::size_of::<CFAllocator>() as CFIndex, CFOptionFlags(0)) as *mut CFRuntimeBase;
+
+    if !instance.is_null() {
+      ptr::write(instance, CFRuntimeBase {
+        type_id: _kCFRuntimeIDCFAllocator,
+        retain_count: AtomicIsize::new(1),
+        allocator: kCFAllocatorUseContext.0.as_ptr()
+      });
+    }
+
+    instance
+  } else {
+    runtime::create_instance(allocator, _kCFRuntimeIDCFAllocator, extra)
+  };
+
+  if instance.is_null() {
+    context_release(context, info);
+
+    return None;
+  }
+
+  ptr::write(&mut (*(instance as *mut CFAllocator)).context, CFAllocatorContext { version: 0, info, ..*context });
+
+  return NonNull::new(instance as *mut c_void).map(CFAllocatorRef);
+}
+
+pub unsafe fn CFAllocatorAllocate(allocator: Option<CFAllocatorRef>, size: CFIndex, hint: CFOptionFlags) -> *mut c_void {
+  if size <= 0 {
+    return ptr::null_mut();
+  }
+
+  let context = context(resolve(pointer(allocator)));
+
+  return context_allocate(context, size, hint);
+}
+
+pub unsafe fn CFAllocatorReallocate(allocator: Option<CFAllocatorRef>, ptr: *mut c_void, newsize: CFIndex, hint: CFOptionFlags) -> *mut c_void {
+  let context = context(resolve(pointer(allocator)));
+
+  if ptr.is_null() {
+    if newsize <= 0 {
+      return ptr::null_mut();
+    }
+
+    return context_allocate(context, newsize, hint);
+  }
+
+  if newsize <= 0 {
+    context_deallocate(context, ptr);
+
+    return ptr::null_mut();
+  }
+
+  return context_reallocate(context, ptr, newsize, hint);
+}
+
+pub unsafe fn CFAllocatorDeallocate(allocator: Option<CFAllocatorRef>, ptr: *mut c_void) {
+  deallocate(pointer(allocator), ptr);
+}
+
+pub unsafe fn CFAllocatorGetPreferredSizeForSize(allocator: Option<CFAllocatorRef>, size: CFIndex, hint: CFOptionFlags) -> CFIndex {
+  let context = context(resolve(pointer(allocator)));
+
+  return match context.preferredSize {
+    Some(preferredSize) => size.max(preferredSize(size, hint, context.info)),
+    None => size
+  };
+}
+
+pub unsafe fn CFAllocatorGetContext(allocator: Option<CFAllocatorRef>, context: *mut CFAllocatorContext) {
+  let source = self::context(resolve(pointer(allocator)));
+
+  ptr::write(context, CFAllocatorContext { version: 0, ..*source });
+}
@@ -0,0 +1,16 @@
+// Pure-Rust implementation of the CoreFoundation object model, standing in for the framework on non-Apple targets.
+// Each submodule provides the same functions and statics as the `ext` module it replaces.
+
+use crate::*;
+
+use std::ptr;
+
+pub mod allocator;
+pub mod null;
+pub mod object;
+pub mod runtime;
+pub mod string;
+
+pub unsafe fn CFCopyTypeIDDescription(type_id: CFTypeID) -> Option<CFStringRef> {
+  return string::create(ptr::null(), &runtime::class_name(type_id));
+}
@@ -0,0 +1,23 @@
+use crate::*;
+
+use std::ptr::{self, NonNull};
+
+use crate::portable::runtime::{CFRuntimeBase, Constant, _kCFRuntimeIDCFNull};
+use crate::portable::string;
+
+pub static CFNullClass: Constant<CFRuntimeClass> = Constant(CFRuntimeClass {
+  copyFormattingDesc: Some(copy_formatting_description),
+  ..CFRuntimeClass::new(c"CFNull")
+});
+
+static Null: Constant<CFRuntimeBase> = Constant(CFRuntimeBase::constant(_kCFRuntimeIDCFNull));
+
+pub static kCFNull: CFNullRef = CFNullRef(unsafe { NonNull::new_unchecked(&Null as *const Constant<CFRuntimeBase> as *mut c_void) });
+
+unsafe extern "C" fn copy_formatting_description(_cf: CFTypeRef, _formatOptions: *const c_void) -> Option<CFStringRef> {
+  return string::create(ptr::null(), "<null>");
+}
+
+pub unsafe fn CFNullGetTypeID() -> CFTypeID {
+  return _kCFRuntimeIDCFNull;
+}
@@ -0,0 +1,50 @@
+use crate::*;
+
+use crate::portable::{runtime, string};
+
+pub unsafe fn CFGetTypeID(cf: CFTypeRef) -> CFTypeID {
+  return runtime::base(cf.0.as_ptr()).type_id;
+}
+
+pub unsafe fn CFRetain(cf: CFTypeRef) -> CFTypeRef {
+  return CFTypeRef(runtime::object(runtime::retain(cf.0.as_ptr())));
+}
+
+pub unsafe fn CFRelease(cf: CFTypeRef) {
+  runtime::release(cf.0.as_ptr());
+}
+
+pub unsafe fn CFAutorelease(arg: CFTypeRef) -> CFTypeRef {
+  runtime::autorelease(arg.0.as_ptr());
+
+  return arg;
+}
+
+pub unsafe fn CFGetRetainCount(cf: CFTypeRef) -> CFIndex {
+  return runtime::retain_count(cf.0.as_ptr());
+}
+
+pub unsafe fn CFEqual(cf1: CFTypeRef, cf2: CFTypeRef) -> Boolean {
+  return Boolean::from(runtime::equal(cf1.0.as_ptr(), cf2.0.as_ptr()));
+}
+
+pub unsafe fn CFHash(cf: CFTypeRef) -> CFHashCode {
+  return runtime::hash(cf.0.as_ptr());
+}
+
+pub unsafe fn CFCopyDescription(cf: CFTypeRef) -> Option<CFStringRef> {
+  return runtime::copy_description(cf.0.as_ptr());
+}
+
+pub unsafe fn CFGetAllocator(cf: CFTypeRef) -> CFAllocatorRef {
+  return CFAllocatorRef(runtime::object(runtime::allocator_of(cf.0.as_ptr())));
+}
+
+pub unsafe fn CFShow(obj: CFTypeRef) {
+  let Some(description) = runtime::copy_formatting_description(obj.0.as_ptr()) else {
+    return;
+  };
+
+  eprintln!("{}", string::contents(description.0.as_ptr()));
+  runtime::release(description.0.as_ptr());
+}
@@ -0,0 +1,239 @@
+use crate::*;
+
+use std::cell::RefCell;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{fence, AtomicIsize, Ordering};
+use std::sync::RwLock;
+
+use crate::portable::{allocator, null, string};
+
+pub const _kCFRuntimeIDCFType: CFTypeID = CFTypeID(1);
+pub const _kCFRuntimeIDCFAllocator: CFTypeID = CFTypeID(2);
+pub const _kCFRuntimeIDCFString: CFTypeID = CFTypeID(7);
+pub const _kCFRuntimeIDCFNull: CFTypeID = CFTypeID(16);
+
+// Classes registered at run time take the type IDs following the builtin ones, up to the size of the class table.
+const kCFRuntimeFirstRegisteredTypeID: unsigned_long = 64;
+const kCFRuntimeClassTableSize: unsigned_long = 1024;
+
+// Objects with this retain count live in static memory and are never freed.
+const kCFRuntimeConstantRetainCount: isize = isize::MAX;
+
+#[repr(C)] pub struct CFRuntimeBase {
+  pub(crate) type_id: CFTypeID,
+  pub(crate) retain_count: AtomicIsize,
+  pub(crate) allocator: *const c_void
+}
+
+impl CFRuntimeBase {
+  pub(crate) const fn constant(type_id: CFTypeID) -> CFRuntimeBase {
+    return CFRuntimeBase {
+      type_id,
+      retain_count: AtomicIsize::new(kCFRuntimeConstantRetainCount),
+      allocator: ptr::null()
+    };
+  }
+}
+
+// Wrapper for objects placed in static memory; CF constants are immutable.
+#[repr(transparent)] pub struct Constant<T>(pub T);
+
+unsafe impl<T> Sync for Constant<T> { }
+
+static CFTypeClass: Constant<CFRuntimeClass> = Constant(CFRuntimeClass::new(c"CFType"));
+
+static RegisteredClasses: RwLock<Vec<&'static CFRuntimeClass>> = RwLock::new(Vec::new());
+
+pub fn class_for(type_id: CFTypeID) -> Option<&'static CFRuntimeClass> {
+  return match type_id {
+    _kCFRuntimeIDCFType => Some(&CFTypeClass.0),
+    _kCFRuntimeIDCFAllocator => Some(&allocator::CFAllocatorClass.0),
+    _kCFRuntimeIDCFString => Some(&string::CFStringClass.0),
+    _kCFRuntimeIDCFNull => Some(&null::CFNullClass.0),
+    CFTypeID(id) if id >= kCFRuntimeFirstRegisteredTypeID => {
+      RegisteredClasses.read().unwrap().get((id - kCFRuntimeFirstRegisteredTypeID) as usize).copied()
+    },
+    _ => None
+  };
+}
+
+pub fn class_name(type_id: CFTypeID) -> String {
+  return match class_for(type_id) {
+    Some(class) => unsafe { std::ffi::CStr::from_ptr(class.className) }.to_string_lossy().into_owned(),
+    None => String::from("NotAType")
+  };
+}
+
+pub fn object(cf: *const c_void) -> NonNull<c_void> {
+  return NonNull::new(cf as *mut c_void).expect("NULL object");
+}
+
+pub unsafe fn base<'a>(cf: *const c_void) -> &'a CFRuntimeBase {
+  return &*(cf as *const CFRuntimeBase);
+}
+
+pub unsafe fn class_of(cf: *const c_void) -> &'static CFRuntimeClass {
+  return class_for(base(cf).type_id).expect("object of unregistered class");
+}
+
+// Allocates an instance with `extra` bytes following its CFRuntimeBase, with a retain count of one.
+pub unsafe fn create_instance(allocator: *const c_void, type_id: CFTypeID, extra: usize) -> *mut CFRuntimeBase {
+  let allocator = allocator::resolve(allocator);
+  let size = mem::size_of::<CFRuntimeBase>() + extra;
+  let instance = allocator::allocate(allocator, size) as *mut CFRuntimeBase;
+
+  if instance.is_null() {
+    return instance;
+  }
+
+  ptr::write(instance, CFRuntimeBase {
+    type_id,
+    retain_count: AtomicIsize::new(1),
+    allocator: retain(allocator)
+  });
+
+  return instance;
+}
+
+pub unsafe fn retain(cf: *const c_void) -> *const c_void {
+  let retain_count = &base(cf).retain_count;
+
+  if retain_count.load(Ordering::Relaxed) != kCFRuntimeConstantRetainCount {
+    retain_count.fetch_add(1, Ordering::Relaxed);
+  }
+
+  return cf;
+}
+
+pub unsafe fn release(cf: *const c_void) {
+  let retain_count = &base(cf).retain_count;
+
+  if retain_count.load(Ordering::Relaxed) == kCFRuntimeConstantRetainCount {
+    return;
+  }
+
+  if retain_count.fetch_sub(1, Ordering::Release) != 1 {
+    return;
+  }
+
+  fence(Ordering::Acquire);
+
+  // Read before finalizing, as allocators created with kCFAllocatorUseContext free themselves while finalizing.
+  let allocator = base(cf).allocator;
+
+  if let Some(finalize) = class_of(cf).finalize {
+    finalize(CFTypeRef(object(cf)));
+  }
+
+  if allocator != allocator::kCFAllocatorUseContext.0.as_ptr() {
+    allocator::deallocate(allocator, cf as *mut c_void);
+    release(allocator);
+  }
+}
+
+pub unsafe fn retain_count(cf: *const c_void) -> CFIndex {
+  return base(cf).retain_count.load(Ordering::Relaxed) as CFIndex;
+}
+
+struct AutoreleasePool(RefCell<Vec<*const c_void>>);
+
+impl Drop for AutoreleasePool {
+  fn drop(&mut self) {
+    for cf in self.0.get_mut().drain(..) {
+      unsafe { release(cf) };
+    }
+  }
+}
+
+thread_local! {
+  static POOL: AutoreleasePool = const { AutoreleasePool(RefCell::new(Vec::new())) };
+}
+
+// There are no run loops to drain a pool, so autoreleased objects live until their thread exits.
+pub unsafe fn autorelease(cf: *const c_void) {
+  let _ = POOL.try_with(|pool| pool.0.borrow_mut().push(cf));
+}
+
+pub unsafe fn equal(cf1: *const c_void, cf2: *const c_void) -> bool {
+  if cf1 == cf2 {
+    return true;
+  }
+
+  if base(cf1).type_id != base(cf2).type_id {
+    return false;
+  }
+
+  return match class_of(cf1).equal {
+    Some(equal) => equal(CFTypeRef(object(cf1)), CFTypeRef(object(cf2))).into(),
+    None => false
+  };
+}
+
+pub unsafe fn hash(cf: *const c_void) -> CFHashCode {
+  return match class_of(cf).hash {
+    Some(hash) => hash(CFTypeRef(object(cf))),
+    None => CFHashCode(cf as usize as _)
+  };
+}
+
+pub unsafe fn copy_description(cf: *const c_void) -> Option<CFStringRef> {
+  if let Some(copyDebugDesc) = class_of(cf).copyDebugDesc {
+    if let Some(description) = copyDebugDesc(CFTypeRef(object(cf))) {
+      return Some(description);
+    }
+  }
+
+  let description = format!("<{} {:p} [{:p}]>", class_name(base(cf).type_id), cf, allocator_of(cf));
+
+  return string::create(ptr::null(), &description);
+}
+
+pub unsafe fn copy_formatting_description(cf: *const c_void) -> Option<CFStringRef> {
+  if let Some(copyFormattingDesc) = class_of(cf).copyFormattingDesc {
+    if let Some(description) = copyFormattingDesc(CFTypeRef(object(cf)), ptr::null()) {
+      return Some(description);
+    }
+  }
+
+  return copy_description(cf);
+}
+
+// Constants report the system default allocator, as they do on Apple platforms.
+pub unsafe fn allocator_of(cf: *const c_void) -> *const c_void {
+  let allocator = base(cf).allocator;
+
+  if allocator.is_null() {
+    return allocator::kCFAllocatorSystemDefault.0.as_ptr();
+  }
+
+  return allocator;
+}
+
+pub unsafe fn _CFRuntimeRegisterClass(cls: *const CFRuntimeClass) -> CFTypeID {
+  let mut classes = RegisteredClasses.write().unwrap();
+  let type_id = kCFRuntimeFirstRegisteredTypeID + classes.len() as unsigned_long;
+
+  if type_id >= kCFRuntimeClassTableSize {
+    return _kCFRuntimeNotATypeID;
+  }
+
+  classes.push(&*cls);
+
+  return CFTypeID(type_id);
+}
+
+// Like the framework, zeroes the extra bytes and runs the class's init callback before returning the instance.
+pub unsafe fn _CFRuntimeCreateInstance(allocator: Option<CFAllocatorRef>, typeID: CFTypeID, extraBytes: CFIndex, _category: *mut u8) -> Option<CFTypeRef> {
+  let class = class_for(typeID)?;
+  let extra = usize::try_from(extraBytes).ok()?;
+  let instance = create_instance(allocator::pointer(allocator), typeID, extra);
+  let cf = CFTypeRef(NonNull::new(instance as *mut c_void)?);
+
+  ptr::write_bytes((instance as *mut u8).add(mem::size_of::<CFRuntimeBase>()), 0, extra);
+
+  if let Some(init) = class.init {
+    init(raw(&cf));
+  }
+
+  return Some(cf);
+}
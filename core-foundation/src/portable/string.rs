@@ -0,0 +1,183 @@
+use crate::*;
+
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::ptr::{self, NonNull};
+use std::slice;
+use std::str;
+
+use crate::portable::runtime::{self, CFRuntimeBase, Constant, _kCFRuntimeIDCFString};
+
+// Same shape as a constant CFString: the base followed by a pointer to the contents and their length.
+// Created strings store their UTF-8 contents inline, directly after this header.
+#[repr(C)] pub struct CFString {
+  pub base: CFRuntimeBase,
+  pub bytes: *const u8,
+  pub length: usize
+}
+
+pub static CFStringClass: Constant<CFRuntimeClass> = Constant(CFRuntimeClass {
+  equal: Some(equal),
+  hash: Some(hash),
+  copyFormattingDesc: Some(copy_formatting_description),
+  copyDebugDesc: Some(copy_debug_description),
+  ..CFRuntimeClass::new(c"CFString")
+});
+
+pub const fn constant(bytes: *const u8, length: usize) -> CFString {
+  return CFString { base: CFRuntimeBase::constant(_kCFRuntimeIDCFString), bytes, length };
+}
+
+pub unsafe fn create(allocator: *const c_void, contents: &str) -> Option<CFStringRef> {
+  let extra = mem::size_of::<CFString>() - mem::size_of::<CFRuntimeBase>() + contents.len();
+  let string = runtime::create_instance(allocator, _kCFRuntimeIDCFString, extra) as *mut CFString;
+
+  if string.is_null() {
+    return None;
+  }
+
+  let bytes = (string as *mut u8).add(mem::size_of::<CFString>());
+
+  ptr::copy_nonoverlapping(contents.as_ptr(), bytes, contents.len());
+  (*string).bytes = bytes;
+  (*string).length = contents.len();
+
+  return NonNull::new(string as *mut c_void).map(CFStringRef);
+}
+
+pub unsafe fn contents<'a>(cf: *const c_void) -> &'a str {
+  let string = &*(cf as *const CFString);
+
+  return str::from_utf8_unchecked(slice::from_raw_parts(string.bytes, string.length));
+}
+
+unsafe extern "C" fn equal(cf1: CFTypeRef, cf2: CFTypeRef) -> Boolean {
+  return Boolean::from(contents(cf1.0.as_ptr()) == contents(cf2.0.as_ptr()));
+}
+
+// 64-bit FNV-1a over the UTF-8 contents, truncated to the width of a CFHashCode.
+unsafe extern "C" fn hash(cf: CFTypeRef) -> CFHashCode {
+  let mut hash: u64 = 0xcbf29ce484222325;
+
+  for byte in contents(cf.0.as_ptr()).bytes() {
+    hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+  }
+
+  return CFHashCode(hash as _);
+}
+
+unsafe extern "C" fn copy_formatting_description(cf: CFTypeRef, _formatOptions: *const c_void) -> Option<CFStringRef> {
+  return Some(CFStringRef(runtime::object(runtime::retain(cf.0.as_ptr()))));
+}
+
+unsafe extern "C" fn copy_debug_description(cf: CFTypeRef) -> Option<CFStringRef> {
+  let description = format!("<CFString {:p} [{:p}]>{{contents = {:?}}}", cf.0.as_ptr(), runtime::allocator_of(cf.0.as_ptr()), contents(cf.0.as_ptr()));
+
+  return create(ptr::null(), &description);
+}
+
+pub unsafe fn CFStringGetTypeID() -> CFTypeID {
+  return _kCFRuntimeIDCFString;
+}
+
+fn pointer(allocator: Option<CFAllocatorRef>) -> *const c_void {
+  return allocator.map_or(ptr::null(), |allocator| allocator.0.as_ptr());
+}
+
+pub unsafe fn CFStringCreateWithCString(alloc: Option<CFAllocatorRef>, cStr: *const c_char, encoding: CFStringEncoding) -> Option<CFStringRef> {
+  let bytes = CStr::from_ptr(cStr).to_bytes();
+
+  return CFStringCreateWithBytes(alloc, bytes.as_ptr(), bytes.len() as CFIndex, encoding, Boolean::FALSE);
+}
+
+// Only UTF-8 and ASCII contents can be decoded; anything else fails as a conversion error would.
+pub unsafe fn CFStringCreateWithBytes(alloc: Option<CFAllocatorRef>, bytes: *const UInt8, numBytes: CFIndex, encoding: CFStringEncoding, _isExternalRepresentation: Boolean) -> Option<CFStringRef> {
+  let bytes = slice::from_raw_parts(bytes, numBytes as usize);
+  let contents = match encoding {
+    CFStringEncoding::kCFStringEncodingUTF8 => str::from_utf8(bytes).ok(),
+    CFStringEncoding::kCFStringEncodingASCII if bytes.is_ascii() => str::from_utf8(bytes).ok(),
+    _ => None
+  };
+
+  return contents.and_then(|contents| create(pointer(alloc), contents));
+}
+
+pub unsafe fn CFStringGetLength(theString: CFStringRef) -> CFIndex {
+  return contents(theString.0.as_ptr()).encode_utf16().count() as CFIndex;
+}
+
+// Only the Unicode encodings and ASCII are available without the framework's conversion tables.
+pub unsafe fn CFStringGetCString(theString: CFStringRef, buffer: *mut c_char, bufferSize: CFIndex, encoding: CFStringEncoding) -> Boolean {
+  let contents = contents(theString.0.as_ptr());
+  let representable = match encoding {
+    CFStringEncoding::kCFStringEncodingUTF8 => true,
+    CFStringEncoding::kCFStringEncodingASCII => contents.is_ascii(),
+    _ => false
+  };
+
+  if !representable || contents.contains('\0') || contents.len() as CFIndex >= bufferSize {
+    return Boolean::FALSE;
+  }
+
+  ptr::copy_nonoverlapping(contents.as_ptr(), buffer as *mut u8, contents.len());
+  *buffer.add(contents.len()) = 0;
+
+  return Boolean::TRUE;
+}
+
+// Converts to UTF-8 or ASCII only, like CFStringGetCString. Characters are counted in UTF-16 units, as CFStringGetLength
+// counts them; a range that splits a surrogate pair leaves its halves unconvertible.
+pub unsafe fn CFStringGetBytes(theString: CFStringRef, range: CFRange, encoding: CFStringEncoding, lossByte: UInt8, _isExternalRepresentation: Boolean, buffer: *mut UInt8, maxBufLen: CFIndex, usedBufLen: *mut CFIndex) -> CFIndex {
+  let ascii = match encoding {
+    CFStringEncoding::kCFStringEncodingUTF8 => false,
+    CFStringEncoding::kCFStringEncodingASCII => true,
+    _ => return 0
+  };
+  let units = contents(theString.0.as_ptr()).encode_utf16().skip(range.location as usize).take(range.length as usize).collect::<Vec<_>>();
+  let mut converted = 0;
+  let mut used = 0;
+
+  for character in char::decode_utf16(units.iter().copied()) {
+    let width = character.as_ref().map_or(1, |character| character.len_utf16());
+    let mut bytes = [0; 4];
+    let bytes: &[u8] = match character {
+      Ok(character) if !ascii || character.is_ascii() => character.encode_utf8(&mut bytes).as_bytes(),
+      _ if lossByte != 0 => slice::from_ref(&lossByte),
+      _ => break
+    };
+
+    if !buffer.is_null() {
+      if used + bytes.len() as CFIndex > maxBufLen {
+        break;
+      }
+
+      ptr::copy_nonoverlapping(bytes.as_ptr(), buffer.add(used as usize), bytes.len());
+    }
+
+    converted += width as CFIndex;
+    used += bytes.len() as CFIndex;
+  }
+
+  if !usedBufLen.is_null() {
+    *usedBufLen = used;
+  }
+
+  return converted;
+}
+
+pub unsafe fn CFStringGetMaximumSizeForEncoding(length: CFIndex, encoding: CFStringEncoding) -> CFIndex {
+  let width = match encoding {
+    CFStringEncoding::kCFStringEncodingUTF8 => 3,
+    CFStringEncoding::kCFStringEncodingUTF16 | CFStringEncoding::kCFStringEncodingUTF16BE | CFStringEncoding::kCFStringEncodingUTF16LE => 2,
+    CFStringEncoding::kCFStringEncodingUTF32 | CFStringEncoding::kCFStringEncodingUTF32BE | CFStringEncoding::kCFStringEncodingUTF32LE => 4,
+    _ => 1
+  };
+
+  return length.checked_mul(width).unwrap_or(kCFNotFound);
+}
+
+pub unsafe fn CFShowStr(string: CFStringRef) {
+  let contents = contents(string.0.as_ptr());
+
+  eprintln!("Length {}\nContents {:?}", contents.chars().map(char::len_utf16).sum::<usize>(), contents);
+}
@@ -0,0 +1,121 @@
+use crate::*;
+
+use std::ops::{Bound, Range, RangeBounds};
+
+#[repr(C)] #[derive(PartialEq, Eq, Debug, Clone, Copy)] pub struct CFRange {
+  pub location: CFIndex,
+  pub length: CFIndex
+}
+
+pub fn CFRangeMake(loc: CFIndex, len: CFIndex) -> CFRange {
+  return CFRange { location: loc, length: len };
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum CFRangeError {
+  // Ends before it starts, or does not fit in a CFIndex.
+  Invalid,
+  // Reaches past the end of a string or collection of `bound` elements.
+  OutOfBounds { location: CFIndex, length: CFIndex, bound: CFIndex }
+}
+
+impl fmt::Display for CFRangeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return match self {
+      CFRangeError::Invalid => write!(f, "range cannot be represented as a CFRange"),
+      CFRangeError::OutOfBounds { location, length, bound } => write!(f, "range of {} from {} is out of bounds for length {}", length, location, bound)
+    };
+  }
+}
+
+impl Error for CFRangeError { }
+
+impl CFRange {
+  // Checks the range lies within a string or collection of `bound` elements, before it reaches CoreFoundation.
+  pub fn validate(self, bound: CFIndex) -> Result<CFRange, CFRangeError> {
+    let error = CFRangeError::OutOfBounds { location: self.location, length: self.length, bound };
+
+    if self.location < 0 || self.length < 0 {
+      return Err(error);
+    }
+
+    return match self.location.checked_add(self.length) {
+      Some(end) if end <= bound => Ok(self),
+      _ => Err(error)
+    };
+  }
+
+  // Resolves any Rust range against a string or collection of `bound` elements, as slicing would.
+  pub fn from_bounds<R: RangeBounds<usize>>(bounds: R, bound: CFIndex) -> Result<CFRange, CFRangeError> {
+    let start = match bounds.start_bound() {
+      Bound::Included(&start) => Some(start),
+      Bound::Excluded(&start) => start.checked_add(1),
+      Bound::Unbounded => Some(0)
+    };
+
+    let end = match bounds.end_bound() {
+      Bound::Included(&end) => end.checked_add(1),
+      Bound::Excluded(&end) => Some(end),
+      Bound::Unbounded => usize::try_from(bound).ok()
+    };
+
+    let (Some(start), Some(end)) = (start, end) else {
+      return Err(CFRangeError::Invalid);
+    };
+
+    return CFRange::try_from(start..end)?.validate(bound);
+  }
+}
+
+impl TryFrom<Range<usize>> for CFRange {
+  type Error = CFRangeError;
+
+  fn try_from(range: Range<usize>) -> Result<CFRange, CFRangeError> {
+    let length = range.end.checked_sub(range.start).ok_or(CFRangeError::Invalid)?;
+    let location = CFIndex::try_from(range.start).map_err(|_| CFRangeError::Invalid)?;
+    let length = CFIndex::try_from(length).map_err(|_| CFRangeError::Invalid)?;
+
+    return CFRange { location, length }.validate(CFIndex::MAX).map_err(|_| CFRangeError::Invalid);
+  }
+}
+
+impl TryFrom<CFRange> for Range<usize> {
+  type Error = CFRangeError;
+
+  fn try_from(range: CFRange) -> Result<Range<usize>, CFRangeError> {
+    let range = range.validate(CFIndex::MAX).map_err(|_| CFRangeError::Invalid)?;
+
+    return Ok(range.location as usize..(range.location + range.length) as usize);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::*;
+
+  use std::ops::Range;
+
+  #[test]
+  fn it_converts_ranges() {
+    assert_eq!(Ok(CFRangeMake(2, 3)), CFRange::try_from(2..5));
+    assert_eq!(Ok(2..5), Range::try_from(CFRangeMake(2, 3)));
+    assert_eq!(Err(CFRangeError::Invalid), Range::try_from(CFRangeMake(-1, 3)));
+    assert_eq!(Err(CFRangeError::Invalid), CFRange::try_from(0..usize::MAX));
+  }
+
+  #[test]
+  fn it_resolves_bounds() {
+    assert_eq!(Ok(CFRangeMake(2, 4)), CFRange::from_bounds(2.., 6));
+    assert_eq!(Ok(CFRangeMake(0, 3)), CFRange::from_bounds(..=2, 6));
+    assert_eq!(Ok(CFRangeMake(0, 6)), CFRange::from_bounds(.., 6));
+    assert_eq!(Err(CFRangeError::Invalid), CFRange::from_bounds(..=usize::MAX, 6));
+  }
+
+  #[test]
+  fn it_checks_bounds() {
+    assert_eq!(Ok(CFRangeMake(6, 0)), CFRangeMake(6, 0).validate(6));
+    assert_eq!(Err(CFRangeError::OutOfBounds { location: 4, length: 3, bound: 6 }), CFRange::from_bounds(4..7, 6));
+    assert!(CFRangeMake(-1, 1).validate(6).is_err());
+    assert!(CFRangeMake(1, CFIndex::MAX).validate(CFIndex::MAX).is_err());
+  }
+}
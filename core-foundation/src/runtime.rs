@@ -0,0 +1,292 @@
+use crate::*;
+
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+#[cfg(target_vendor = "apple")]
+pub(crate) mod ext {
+  use crate::*;
+
+  cf_extern! {
+    pub fn _CFRuntimeRegisterClass(cls: *const CFRuntimeClass) -> CFTypeID;
+    pub fn _CFRuntimeCreateInstance(allocator: Option<CFAllocatorRef>, typeID: CFTypeID, extraBytes: CFIndex, category: *mut u8) -> Option<CFTypeRef>;
+  }
+}
+
+#[cfg(not(target_vendor = "apple"))]
+pub(crate) use crate::portable::runtime as ext;
+
+#[cfg(not(feature = "backend"))]
+use self::ext as ffi;
+
+#[cfg(feature = "backend")]
+use crate::backend::runtime as ffi;
+
+pub const _kCFRuntimeNotATypeID: CFTypeID = CFTypeID(0);
+
+// Opaque header at the start of every instance, as declared in CFRuntime.h.
+#[cfg(target_vendor = "apple")]
+#[repr(C)] pub struct CFRuntimeBase {
+  _cfisa: usize,
+  _cfinfo: [u8; 4],
+  #[cfg(target_pointer_width = "64")]
+  _rc: u32
+}
+
+#[cfg(not(target_vendor = "apple"))]
+pub use crate::portable::runtime::CFRuntimeBase;
+
+// Mirrors the layout of CFRuntimeClass in CFRuntime.h.
+#[repr(C)] pub struct CFRuntimeClass {
+  pub version: CFIndex,
+  pub className: *const c_char,
+  pub init: Option<unsafe extern "C" fn(cf: CFTypeRef)>,
+  pub copy: Option<unsafe extern "C" fn(allocator: CFAllocatorRef, cf: CFTypeRef) -> CFTypeRef>,
+  pub finalize: Option<unsafe extern "C" fn(cf: CFTypeRef)>,
+  pub equal: Option<unsafe extern "C" fn(cf1: CFTypeRef, cf2: CFTypeRef) -> Boolean>,
+  pub hash: Option<unsafe extern "C" fn(cf: CFTypeRef) -> CFHashCode>,
+  pub copyFormattingDesc: Option<unsafe extern "C" fn(cf: CFTypeRef, formatOptions: *const c_void) -> Option<CFStringRef>>,
+  pub copyDebugDesc: Option<unsafe extern "C" fn(cf: CFTypeRef) -> Option<CFStringRef>>,
+  pub reclaim: Option<unsafe extern "C" fn(cf: CFTypeRef)>,
+  pub refcount: Option<unsafe extern "C" fn(op: isize, cf: CFTypeRef) -> u32>,
+  pub requiredAlignment: usize
+}
+
+// The class name always points to a static string.
+unsafe impl Sync for CFRuntimeClass { }
+
+impl CFRuntimeClass {
+  pub const fn new(className: &'static CStr) -> CFRuntimeClass {
+    return CFRuntimeClass {
+      version: 0,
+      className: className.as_ptr(),
+      init: None,
+      copy: None,
+      finalize: None,
+      equal: None,
+      hash: None,
+      copyFormattingDesc: None,
+      copyDebugDesc: None,
+      reclaim: None,
+      refcount: None,
+      requiredAlignment: 0
+    };
+  }
+}
+
+pub unsafe fn _CFRuntimeRegisterClass(cls: &'static CFRuntimeClass) -> Option<CFTypeID> {
+  let type_id = ffi::_CFRuntimeRegisterClass(cls);
+
+  return if type_id == _kCFRuntimeNotATypeID { None } else { Some(type_id) };
+}
+
+pub unsafe fn _CFRuntimeCreateInstance(allocator: Option<&CFAllocatorRef>, typeID: CFTypeID, extraBytes: CFIndex) -> Option<CFOwned<CFTypeRef>> {
+  return ffi::_CFRuntimeCreateInstance(allocator.map(raw), typeID, extraBytes, ptr::null_mut()).map(|cf| CFOwned::from_create_rule(cf));
+}
+
+// Rust values stored inline in instances of a registered class, dropped when the instance is finalized.
+// `Ref` is the handle type for instances, whose type ID function should return CFRuntimeRegisterType's. Instances are
+// only created, and values only read, when the two agree. Values are Send and Sync, as the last release may come from
+// any thread and handles may be shared.
+pub trait CFRuntimeType : Send + Sync + Sized + 'static {
+  type Ref: CFClass;
+
+  const CLASS_NAME: &'static CStr;
+
+  fn equal(&self, other: &Self) -> bool {
+    return ptr::eq(self, other);
+  }
+
+  fn hash(&self) -> CFHashCode {
+    return CFHashCode(self as *const Self as usize as _);
+  }
+
+  // None leaves the runtime to describe instances by class name and address.
+  fn description(&self) -> Option<String> {
+    return None;
+  }
+}
+
+// Instances are aligned at least this much by every allocator the runtime uses.
+const kCFRuntimeInstanceAlignment: usize = 16;
+
+fn offset<T: CFRuntimeType>() -> usize {
+  return mem::size_of::<CFRuntimeBase>().next_multiple_of(mem::align_of::<T>());
+}
+
+fn value<T: CFRuntimeType>(cf: &CFTypeRef) -> *mut T {
+  return unsafe { (cf.0.as_ptr() as *mut u8).add(offset::<T>()) as *mut T };
+}
+
+unsafe extern "C" fn finalize<T: CFRuntimeType>(cf: CFTypeRef) {
+  ptr::drop_in_place(value::<T>(&cf));
+}
+
+unsafe extern "C" fn equal<T: CFRuntimeType>(cf1: CFTypeRef, cf2: CFTypeRef) -> Boolean {
+  return Boolean::from((*value::<T>(&cf1)).equal(&*value::<T>(&cf2)));
+}
+
+unsafe extern "C" fn hash<T: CFRuntimeType>(cf: CFTypeRef) -> CFHashCode {
+  return (*value::<T>(&cf)).hash();
+}
+
+unsafe extern "C" fn copy_debug_description<T: CFRuntimeType>(cf: CFTypeRef) -> Option<CFStringRef> {
+  let description = (*value::<T>(&cf)).description()?;
+
+  return CFStringCreateWithBytes(kCFAllocatorDefault, description.as_bytes(), CFStringEncoding::kCFStringEncodingUTF8, false).map(CFOwned::into_raw);
+}
+
+static RegisteredTypes: OnceLock<Mutex<HashMap<TypeId, CFTypeID>>> = OnceLock::new();
+
+// Registers a class for `T` the first time it is called for it, and returns the same type ID from then on.
+pub fn CFRuntimeRegisterType<T: CFRuntimeType>() -> Option<CFTypeID> {
+  assert!(mem::align_of::<T>() <= kCFRuntimeInstanceAlignment, "{:?} requires more alignment than instances have", T::CLASS_NAME);
+
+  let mut types = RegisteredTypes.get_or_init(Default::default).lock().unwrap_or_else(PoisonError::into_inner);
+
+  if let Some(type_id) = types.get(&TypeId::of::<T>()) {
+    return Some(*type_id);
+  }
+
+  let class = Box::leak(Box::new(CFRuntimeClass {
+    finalize: Some(finalize::<T>),
+    equal: Some(equal::<T>),
+    hash: Some(hash::<T>),
+    copyDebugDesc: Some(copy_debug_description::<T>),
+    ..CFRuntimeClass::new(T::CLASS_NAME)
+  }));
+  let type_id = unsafe { _CFRuntimeRegisterClass(class)? };
+
+  types.insert(TypeId::of::<T>(), type_id);
+
+  return Some(type_id);
+}
+
+fn registered<T: CFRuntimeType>() -> Option<CFTypeID> {
+  return RegisteredTypes.get()?.lock().unwrap_or_else(PoisonError::into_inner).get(&TypeId::of::<T>()).copied();
+}
+
+pub fn CFRuntimeCreateInstance<T: CFRuntimeType>(allocator: Option<&CFAllocatorRef>, value: T) -> Option<CFOwned<T::Ref>> {
+  let type_id = CFRuntimeRegisterType::<T>()?;
+  let extraBytes = offset::<T>() - mem::size_of::<CFRuntimeBase>() + mem::size_of::<T>();
+
+  if T::Ref::type_id() != type_id {
+    return None;
+  }
+
+  return unsafe {
+    let cf = _CFRuntimeCreateInstance(allocator, type_id, extraBytes as CFIndex)?.into_raw();
+
+    ptr::write(self::value::<T>(&cf), value);
+    Some(CFOwned::from_create_rule(ptr::read(&cf as *const CFTypeRef as *const T::Ref)))
+  };
+}
+
+// None unless `cf` is an instance of the class registered for `T`.
+pub fn CFRuntimeGetValue<T: CFRuntimeType>(cf: &T::Ref) -> Option<&T> {
+  if registered::<T>() != Some(CFGetTypeID(cf)) {
+    return None;
+  }
+
+  return Some(unsafe { &*value::<T>(cf.upcast()) });
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::*;
+
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::OnceLock;
+
+  static FINALIZED: AtomicUsize = AtomicUsize::new(0);
+
+  struct Point {
+    x: i32,
+    y: i32
+  }
+
+  impl Drop for Point {
+    fn drop(&mut self) {
+      FINALIZED.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+
+  impl CFRuntimeType for Point {
+    type Ref = PointRef;
+
+    const CLASS_NAME: &'static CStr = c"Point";
+
+    fn equal(&self, other: &Point) -> bool {
+      return self.x == other.x && self.y == other.y;
+    }
+
+    fn hash(&self) -> CFHashCode {
+      return CFHashCode((self.x as u32 ^ (self.y as u32).rotate_left(16)) as _);
+    }
+
+    fn description(&self) -> Option<String> {
+      return Some(format!("<Point ({}, {})>", self.x, self.y));
+    }
+  }
+
+  fn PointGetTypeID() -> CFTypeID {
+    static TYPE_ID: OnceLock<CFTypeID> = OnceLock::new();
+
+    return *TYPE_ID.get_or_init(|| CFRuntimeRegisterType::<Point>().unwrap());
+  }
+
+  cf_class! {
+    unsafe class PointRef: CFTypeRef = PointGetTypeID;
+  }
+
+  // Claims instances of PointRef without being what they hold.
+  struct Impostor;
+
+  impl CFRuntimeType for Impostor {
+    type Ref = PointRef;
+
+    const CLASS_NAME: &'static CStr = c"Impostor";
+  }
+
+  #[test]
+  fn it_registers_types_once() {
+    assert_eq!(PointGetTypeID(), CFRuntimeRegisterType::<Point>().unwrap());
+    assert_ne!(PointGetTypeID(), CFRuntimeRegisterType::<Impostor>().unwrap());
+  }
+
+  #[test]
+  fn it_checks_instance_types() {
+    let point = CFRuntimeCreateInstance(kCFAllocatorDefault, Point { x: 3, y: 4 }).unwrap();
+
+    assert!(CFRuntimeCreateInstance(kCFAllocatorDefault, Impostor).is_none());
+    assert!(CFRuntimeGetValue::<Impostor>(&point).is_none());
+  }
+
+  #[test]
+  fn it_rejects_negative_sizes() {
+    assert!(unsafe { _CFRuntimeCreateInstance(kCFAllocatorDefault, PointGetTypeID(), -1) }.is_none());
+  }
+
+  #[test]
+  fn it_registers_types() {
+    let point = CFRuntimeCreateInstance(kCFAllocatorDefault, Point { x: 3, y: 4 }).unwrap();
+    let other = CFRuntimeCreateInstance(kCFAllocatorDefault, Point { x: 3, y: 4 }).unwrap();
+
+    assert_eq!(PointGetTypeID(), CFGetTypeID(&*point));
+    assert_eq!("Point", String::from(&*CFCopyTypeIDDescription(PointGetTypeID()).unwrap()));
+    assert_eq!(4, CFRuntimeGetValue::<Point>(&point).unwrap().y);
+    assert!(CFEqual(&*point, &*other));
+    assert_eq!(CFHash(&*point), CFHash(&*other));
+    assert_eq!("<Point (3, 4)>", String::from(&*CFCopyDescription(&*point).unwrap()));
+    assert!(Subtype::<CFTypeRef>::upcast(&*point).downcast::<PointRef>().is_ok());
+
+    CFShow(&*point);
+
+    let finalized = FINALIZED.load(Ordering::Relaxed);
+
+    mem::drop(point);
+    mem::drop(other);
+    assert_eq!(finalized + 2, FINALIZED.load(Ordering::Relaxed));
+  }
+}
@@ -0,0 +1,265 @@
+// An allocator that counts what it hands out through another, so that memory use can be put down to whatever
+// allocates with it: live and peak bytes, the number of calls and how large the blocks asked for were.
+
+use crate::*;
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+// Sizes are counted in powers of two: bucket `n` holds requests of up to 2^n bytes, and more than half that.
+pub const kCFAllocatorStatisticsBuckets: usize = usize::BITS as usize + 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CFAllocatorStatistics {
+  pub live_bytes: usize,
+  pub peak_bytes: usize,
+  pub allocations: u64,
+  pub reallocations: u64,
+  pub deallocations: u64,
+  pub histogram: [u64; kCFAllocatorStatisticsBuckets]
+}
+
+impl CFAllocatorStatistics {
+  pub const fn new() -> CFAllocatorStatistics {
+    return CFAllocatorStatistics { live_bytes: 0, peak_bytes: 0, allocations: 0, reallocations: 0, deallocations: 0, histogram: [0; kCFAllocatorStatisticsBuckets] };
+  }
+
+  pub fn bucket(size: usize) -> usize {
+    return size.checked_next_power_of_two().map_or(kCFAllocatorStatisticsBuckets - 1, |size| size.trailing_zeros() as usize);
+  }
+
+  fn record(&mut self, size: usize) {
+    self.histogram[CFAllocatorStatistics::bucket(size)] += 1;
+    self.peak_bytes = self.peak_bytes.max(self.live_bytes);
+  }
+}
+
+impl Default for CFAllocatorStatistics {
+  fn default() -> CFAllocatorStatistics {
+    return CFAllocatorStatistics::new();
+  }
+}
+
+fn lock(statistics: &Mutex<CFAllocatorStatistics>) -> MutexGuard<'_, CFAllocatorStatistics> {
+  return statistics.lock().unwrap_or_else(PoisonError::into_inner);
+}
+
+// Blocks from the wrapped allocator are prefixed with the size asked for, as deallocation does not pass it on.
+struct Counter {
+  allocator: CFOwned<CFAllocatorRef>,
+  statistics: Arc<Mutex<CFAllocatorStatistics>>
+}
+
+impl Counter {
+  unsafe fn block(ptr: *mut c_void) -> (*mut c_void, usize) {
+    let block = (ptr as *mut u8).sub(kCFAllocatorBlockAlignment);
+
+    return (block as *mut c_void, *(block as *const usize));
+  }
+
+  unsafe fn place(block: *mut c_void, size: CFIndex) -> *mut c_void {
+    *(block as *mut usize) = size as usize;
+
+    return (block as *mut u8).add(kCFAllocatorBlockAlignment) as *mut c_void;
+  }
+}
+
+// The size asked of the wrapped allocator, None for sizes no block can have.
+fn padded(size: CFIndex) -> Option<CFIndex> {
+  if size < 0 {
+    return None;
+  }
+
+  return size.checked_add(kCFAllocatorBlockAlignment as CFIndex);
+}
+
+unsafe impl CFAllocatorCallbacks for Counter {
+  fn allocate(&self, size: CFIndex, hint: CFOptionFlags) -> *mut c_void {
+    let block = match padded(size) {
+      Some(padded) => unsafe { CFAllocatorAllocate(Some(&self.allocator), padded, hint) },
+      None => return ptr::null_mut()
+    };
+
+    if block.is_null() {
+      return ptr::null_mut();
+    }
+
+    let mut statistics = lock(&self.statistics);
+
+    statistics.allocations += 1;
+    statistics.live_bytes += size as usize;
+    statistics.record(size as usize);
+
+    return unsafe { Counter::place(block, size) };
+  }
+
+  fn reallocate(&self, ptr: *mut c_void, size: CFIndex, hint: CFOptionFlags) -> *mut c_void {
+    let padded = match padded(size) {
+      Some(padded) => padded,
+      None => return ptr::null_mut()
+    };
+    let (block, previous) = unsafe { Counter::block(ptr) };
+    let block = unsafe { CFAllocatorReallocate(Some(&self.allocator), block, padded, hint) };
+
+    if block.is_null() {
+      return ptr::null_mut();
+    }
+
+    let mut statistics = lock(&self.statistics);
+
+    statistics.reallocations += 1;
+    statistics.live_bytes = (statistics.live_bytes + size as usize).saturating_sub(previous);
+    statistics.record(size as usize);
+
+    return unsafe { Counter::place(block, size) };
+  }
+
+  fn deallocate(&self, ptr: *mut c_void) {
+    let (block, size) = unsafe { Counter::block(ptr) };
+
+    unsafe { CFAllocatorDeallocate(Some(&self.allocator), block) };
+
+    let mut statistics = lock(&self.statistics);
+
+    statistics.deallocations += 1;
+    statistics.live_bytes = statistics.live_bytes.saturating_sub(size);
+  }
+
+  // Sizes that cannot be padded come back unchanged, as they cannot be allocated either.
+  fn preferred_size(&self, size: CFIndex, hint: CFOptionFlags) -> CFIndex {
+    let Some(padded) = padded(size) else {
+      return size;
+    };
+
+    return CFAllocatorGetPreferredSizeForSize(Some(&self.allocator), padded, hint) - kCFAllocatorBlockAlignment as CFIndex;
+  }
+
+  fn description(&self) -> String {
+    return format!("<CFStatisticsAllocator {:?}>", self.allocator);
+  }
+}
+
+pub struct CFStatisticsAllocator {
+  allocator: CFOwned<CFAllocatorRef>,
+  statistics: Arc<Mutex<CFAllocatorStatistics>>
+}
+
+impl CFStatisticsAllocator {
+  // Wraps `allocator`, or the default at the time for None, which stays the one wrapped after the new allocator has
+  // been made the default in turn.
+  pub fn new(allocator: Option<&CFAllocatorRef>) -> Option<CFStatisticsAllocator> {
+    let wrapped = match allocator {
+      Some(allocator) => CFRetain(allocator),
+      None => CFAllocatorGetDefault()
+    };
+    let statistics = Arc::new(Mutex::new(CFAllocatorStatistics::new()));
+    let allocator = CFAllocatorBuilder::new(Counter { allocator: wrapped, statistics: statistics.clone() }).create()?;
+
+    return Some(CFStatisticsAllocator { allocator, statistics });
+  }
+
+  pub fn allocator(&self) -> &CFAllocatorRef {
+    return &self.allocator;
+  }
+
+  pub fn statistics(&self) -> CFAllocatorStatistics {
+    return lock(&self.statistics).clone();
+  }
+
+  // Starts counting afresh. Blocks still out stay live, and bring down the live bytes once deallocated.
+  pub fn reset(&self) {
+    let mut statistics = lock(&self.statistics);
+    let live_bytes = statistics.live_bytes;
+
+    *statistics = CFAllocatorStatistics { live_bytes, peak_bytes: live_bytes, ..CFAllocatorStatistics::new() };
+  }
+
+  // Counts everything allocated with the default allocator on this thread while `body` runs.
+  pub fn install<R>(&self, body: impl FnOnce() -> R) -> R {
+    return with_default_allocator(&*self.allocator, body);
+  }
+}
+
+impl fmt::Debug for CFStatisticsAllocator {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return f.debug_struct("CFStatisticsAllocator").field("allocator", &self.allocator).field("statistics", &self.statistics()).finish();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::*;
+
+  #[test]
+  fn it_counts_allocations() {
+    let allocator = CFStatisticsAllocator::new(kCFAllocatorDefault).unwrap();
+
+    unsafe {
+      let block = CFAllocatorAllocate(Some(allocator.allocator()), 100, CFOptionFlags(0));
+      let statistics = allocator.statistics();
+
+      assert_eq!((100, 100, 1), (statistics.live_bytes, statistics.peak_bytes, statistics.allocations));
+      assert_eq!(1, statistics.histogram[7]);
+
+      let block = CFAllocatorReallocate(Some(allocator.allocator()), block, 40, CFOptionFlags(0));
+      let statistics = allocator.statistics();
+
+      assert_eq!((40, 100, 1), (statistics.live_bytes, statistics.peak_bytes, statistics.reallocations));
+      assert_eq!(1, statistics.histogram[6]);
+
+      allocator.reset();
+      CFAllocatorDeallocate(Some(allocator.allocator()), block);
+    }
+
+    assert_eq!(CFAllocatorStatistics { deallocations: 1, peak_bytes: 40, ..CFAllocatorStatistics::new() }, allocator.statistics());
+  }
+
+  #[test]
+  fn it_rejects_impossible_sizes() {
+    let allocator = CFStatisticsAllocator::new(kCFAllocatorDefault).unwrap();
+    let builder = CFAllocatorBuilder::new(super::Counter { allocator: CFRetain(&*kCFAllocatorSystemDefault), statistics: Default::default() });
+    let context = builder.context();
+
+    unsafe {
+      assert!(CFAllocatorAllocate(Some(allocator.allocator()), CFIndex::MAX, CFOptionFlags(0)).is_null());
+      assert_eq!(CFIndex::MAX, CFAllocatorGetPreferredSizeForSize(Some(allocator.allocator()), CFIndex::MAX, CFOptionFlags(0)));
+      assert_eq!(-1, context.preferredSize.unwrap()(-1, CFOptionFlags(0), context.info));
+      assert!(context.allocate.unwrap()(-1, CFOptionFlags(0), context.info).is_null());
+
+      let block = CFAllocatorAllocate(Some(allocator.allocator()), 8, CFOptionFlags(0));
+
+      assert!(CFAllocatorReallocate(Some(allocator.allocator()), block, CFIndex::MAX - 1, CFOptionFlags(0)).is_null());
+      CFAllocatorDeallocate(Some(allocator.allocator()), block);
+    }
+
+    let statistics = allocator.statistics();
+
+    assert_eq!((0, 1, 0, 1), (statistics.live_bytes, statistics.allocations, statistics.reallocations, statistics.deallocations));
+    assert_eq!(1, statistics.histogram.iter().sum::<u64>());
+  }
+
+  #[test]
+  fn it_installs_as_the_default() {
+    let allocator = CFStatisticsAllocator::new(kCFAllocatorDefault).unwrap();
+    let retain_count = CFGetRetainCount(allocator.allocator());
+    let previous = CFAllocatorGetDefault();
+    let string = allocator.install(|| {
+      assert!(CFEqual(allocator.allocator(), &*CFAllocatorGetDefault()));
+      CFStringCreateWithCString(kCFAllocatorDefault, c"hagane", CFStringEncoding::kCFStringEncodingUTF8).unwrap()
+    });
+
+    assert!(CFEqual(&*previous, &*CFAllocatorGetDefault()));
+    assert!(allocator.statistics().live_bytes > 0);
+
+    drop(string);
+    assert_eq!(0, allocator.statistics().live_bytes);
+    assert_eq!(retain_count, CFGetRetainCount(allocator.allocator()));
+  }
+
+  #[test]
+  fn it_buckets_sizes() {
+    assert_eq!(0, CFAllocatorStatistics::bucket(1));
+    assert_eq!(4, CFAllocatorStatistics::bucket(16));
+    assert_eq!(5, CFAllocatorStatistics::bucket(17));
+    assert_eq!(kCFAllocatorStatisticsBuckets - 1, CFAllocatorStatistics::bucket(usize::MAX));
+  }
+}
@@ -0,0 +1,145 @@
+use crate::*;
+
+// Result codes returned by OS functions, as declared in MacTypes.h. Wrappers around functions returning a status
+// return `Result<T, OSStatus>` instead, with `noErr` as the only success.
+#[repr(transparent)] #[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)] pub struct OSStatus(i32);
+#[repr(transparent)] #[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)] pub struct OSErr(i16);
+
+pub const noErr: OSStatus = OSStatus(0);
+
+// Well-known codes from MacErrors.h and SecBase.h, sorted by code.
+const kOSStatusNames: &[(i32, &str)] = &[
+  (-25300, "errSecItemNotFound"),
+  (-25299, "errSecDuplicateItem"),
+  (-25293, "errSecAuthFailed"),
+  (-25291, "errSecNotAvailable"),
+  (-4960, "coreFoundationUnknownErr"),
+  (-1708, "errAEEventNotHandled"),
+  (-192, "resNotFound"),
+  (-128, "userCanceledErr"),
+  (-120, "dirNFErr"),
+  (-111, "memWZErr"),
+  (-109, "nilHandleErr"),
+  (-108, "memFullErr"),
+  (-61, "wrPermErr"),
+  (-54, "permErr"),
+  (-51, "rfNumErr"),
+  (-50, "paramErr"),
+  (-49, "opWrErr"),
+  (-48, "dupFNErr"),
+  (-47, "fBsyErr"),
+  (-45, "fLckdErr"),
+  (-44, "wPrErr"),
+  (-43, "fnfErr"),
+  (-42, "tmfoErr"),
+  (-40, "posErr"),
+  (-39, "eofErr"),
+  (-38, "fnOpnErr"),
+  (-37, "bdNamErr"),
+  (-36, "ioErr"),
+  (-35, "nsvErr"),
+  (-34, "dskFulErr"),
+  (-27, "abortErr"),
+  (-23, "openErr"),
+  (-20, "writErr"),
+  (-19, "readErr"),
+  (-4, "unimpErr"),
+  (0, "noErr")
+];
+
+fn name(code: i32) -> Option<&'static str> {
+  return kOSStatusNames.binary_search_by_key(&code, |&(code, _)| code).ok().map(|index| kOSStatusNames[index].1);
+}
+
+impl OSStatus {
+  pub const fn new(code: i32) -> OSStatus {
+    return OSStatus(code);
+  }
+
+  pub const fn code(self) -> i32 {
+    return self.0;
+  }
+
+  pub fn name(self) -> Option<&'static str> {
+    return name(self.0);
+  }
+
+  pub fn result(self) -> Result<(), OSStatus> {
+    return if self == noErr { Ok(()) } else { Err(self) };
+  }
+}
+
+impl OSErr {
+  pub const fn new(code: i16) -> OSErr {
+    return OSErr(code);
+  }
+
+  pub const fn code(self) -> i16 {
+    return self.0;
+  }
+
+  pub fn name(self) -> Option<&'static str> {
+    return name(self.0 as i32);
+  }
+
+  pub fn result(self) -> Result<(), OSStatus> {
+    return OSStatus::from(self).result();
+  }
+}
+
+impl From<OSErr> for OSStatus {
+  fn from(err: OSErr) -> OSStatus {
+    return OSStatus(err.0 as i32);
+  }
+}
+
+// Many frameworks return four-char codes as statuses, which read better as their characters.
+impl fmt::Display for OSStatus {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.0)?;
+
+    let bytes = self.0.to_be_bytes();
+
+    if bytes.iter().all(|byte| byte.is_ascii_graphic() || *byte == b' ') {
+      write!(f, " '{}'", bytes.iter().map(|&byte| byte as char).collect::<String>())?;
+    }
+
+    if let Some(name) = self.name() {
+      write!(f, " ({})", name)?;
+    }
+
+    return Ok(());
+  }
+}
+
+impl fmt::Display for OSErr {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return match self.name() {
+      Some(name) => write!(f, "{} ({})", self.0, name),
+      None => write!(f, "{}", self.0)
+    };
+  }
+}
+
+impl Error for OSStatus { }
+impl Error for OSErr { }
+
+#[cfg(test)]
+mod tests {
+  use crate::*;
+
+  #[test]
+  fn it_formats_statuses() {
+    assert_eq!("-50 (paramErr)", OSStatus::new(-50).to_string());
+    assert_eq!("1718449215 'fmt?'", OSStatus::new(0x666d743f).to_string());
+    assert_eq!("-43 (fnfErr)", OSErr::new(-43).to_string());
+    assert_eq!("-12345", OSErr::new(-12345).to_string());
+  }
+
+  #[test]
+  fn it_returns_results() {
+    assert_eq!(Ok(()), noErr.result());
+    assert_eq!(Err(OSStatus::new(-108)), OSErr::new(-108).result());
+    assert_eq!(Some("memFullErr"), OSStatus::new(-108).name());
+  }
+}
@@ -1,6 +1,6 @@
 use crate::*;
 
-#[repr(u32)] enum CFStringEncoding {
+#[repr(u32)] pub enum CFStringEncoding {
   kCFStringEncodingMacRoman = 0,
   kCFStringEncodingMacJapanese = 1,
   kCFStringEncodingMacChineseTrad = 2,
@@ -176,7 +176,9 @@ use crate::*;
 //   kCFCompareForcedOrdering = 512
 // }
 
-#[repr(u64)] enum CFStringNormalizationForm {
+#[cfg_attr(target_pointer_width = "64", repr(i64))]
+#[cfg_attr(target_pointer_width = "32", repr(i32))]
+pub enum CFStringNormalizationForm {
   kCFStringNormalizationFormD = 0,
   kCFStringNormalizationFormKD = 1,
   kCFStringNormalizationFormC = 2,
@@ -184,15 +186,16 @@ use crate::*;
 }
 
 
-mod ext {
+#[cfg(target_vendor = "apple")]
+pub(crate) mod ext {
   use crate::*;
 
-  extern {
+  cf_extern! {
     pub fn CFStringGetTypeID() -> CFTypeID;
 
     // CFStringRef CFStringCreateWithPascalString(CFAllocatorRef alloc, ConstStr255Param pStr, CFStringEncoding encoding);
-    // CFStringRef CFStringCreateWithCString(CFAllocatorRef alloc, const char *cStr, CFStringEncoding encoding);
-    // CFStringRef CFStringCreateWithBytes(CFAllocatorRef alloc, const UInt8 *bytes, CFIndex numBytes, CFStringEncoding encoding, Boolean isExternalRepresentation);
+    pub fn CFStringCreateWithCString(alloc: Option<CFAllocatorRef>, cStr: *const c_char, encoding: CFStringEncoding) -> Option<CFStringRef>;
+    pub fn CFStringCreateWithBytes(alloc: Option<CFAllocatorRef>, bytes: *const UInt8, numBytes: CFIndex, encoding: CFStringEncoding, isExternalRepresentation: Boolean) -> Option<CFStringRef>;
     // CFStringRef CFStringCreateWithCharacters(CFAllocatorRef alloc, const UniChar *chars, CFIndex numChars);
     // CFStringRef CFStringCreateWithPascalStringNoCopy(CFAllocatorRef alloc, ConstStr255Param pStr, CFStringEncoding encoding, CFAllocatorRef contentsDeallocator);
     // CFStringRef CFStringCreateWithCStringNoCopy(CFAllocatorRef alloc, const char *cStr, CFStringEncoding encoding, CFAllocatorRef contentsDeallocator);
@@ -206,21 +209,21 @@ mod ext {
     // CFMutableStringRef CFStringCreateMutableCopy(CFAllocatorRef alloc, CFIndex maxLength, CFStringRef theString);
     // CFMutableStringRef CFStringCreateMutableWithExternalCharactersNoCopy(CFAllocatorRef alloc, UniChar *chars, CFIndex numChars, CFIndex capacity, CFAllocatorRef externalCharactersAllocator);
     // 
-    // CFIndex CFStringGetLength(CFStringRef theString);
+    pub fn CFStringGetLength(theString: CFStringRef) -> CFIndex;
     // UniChar CFStringGetCharacterAtIndex(CFStringRef theString, CFIndex idx);
     // void CFStringGetCharacters(CFStringRef theString, CFRange range, UniChar *buffer);
     // Boolean CFStringGetPascalString(CFStringRef theString, StringPtr buffer, CFIndex bufferSize, CFStringEncoding encoding);
-    // Boolean CFStringGetCString(CFStringRef theString, char *buffer, CFIndex bufferSize, CFStringEncoding encoding);
+    pub fn CFStringGetCString(theString: CFStringRef, buffer: *mut c_char, bufferSize: CFIndex, encoding: CFStringEncoding) -> Boolean;
     // ConstStringPtr CFStringGetPascalStringPtr(CFStringRef theString, CFStringEncoding encoding);
     // const char *CFStringGetCStringPtr(CFStringRef theString, CFStringEncoding encoding);
     // const UniChar *CFStringGetCharactersPtr(CFStringRef theString);
-    // CFIndex CFStringGetBytes(CFStringRef theString, CFRange range, CFStringEncoding encoding, UInt8 lossByte, Boolean isExternalRepresentation, UInt8 *buffer, CFIndex maxBufLen, CFIndex *usedBufLen);
+    pub fn CFStringGetBytes(theString: CFStringRef, range: CFRange, encoding: CFStringEncoding, lossByte: UInt8, isExternalRepresentation: Boolean, buffer: *mut UInt8, maxBufLen: CFIndex, usedBufLen: *mut CFIndex) -> CFIndex;
     // CFStringRef CFStringCreateFromExternalRepresentation(CFAllocatorRef alloc, CFDataRef data, CFStringEncoding encoding);	/* May return NULL on conversion error */
     // CFDataRef CFStringCreateExternalRepresentation(CFAllocatorRef alloc, CFStringRef theString, CFStringEncoding encoding, UInt8 lossByte);	/* May return NULL on conversion error */	
     // CFStringEncoding CFStringGetSmallestEncoding(CFStringRef theString);	/* Result in O(n) time max */
     // CFStringEncoding CFStringGetFastestEncoding(CFStringRef theString);	/* Result in O(1) time max */
     // CFStringEncoding CFStringGetSystemEncoding(void);		/* The default encoding for the system; untagged 8-bit characters are usually in this encoding */
-    // CFIndex CFStringGetMaximumSizeForEncoding(CFIndex length, CFStringEncoding encoding);	/* Max bytes a string of specified length (in UniChars) will take up if encoded */
+    pub fn CFStringGetMaximumSizeForEncoding(length: CFIndex, encoding: CFStringEncoding) -> CFIndex;	/* Max bytes a string of specified length (in UniChars) will take up if encoded */
     // Boolean CFStringGetFileSystemRepresentation(CFStringRef string, char *buffer, CFIndex maxBufLen);
     // CFIndex CFStringGetMaximumSizeOfFileSystemRepresentation(CFStringRef string);
     // CFStringRef CFStringCreateWithFileSystemRepresentation(CFAllocatorRef alloc, const char *buffer);
@@ -298,39 +301,348 @@ mod ext {
     // CFStringRef  CFStringConvertEncodingToIANACharSetName(CFStringEncoding encoding);
     // CFStringEncoding CFStringGetMostCompatibleMacStringEncoding(CFStringEncoding encoding);
 
-    fn CFShowStr(string: CFStringRef);
+    pub fn CFShowStr(string: CFStringRef);
+  }
+
+  // Only the address of the class is used, as the isa of constant strings.
+  #[cfg(not(feature = "dlopen"))]
+  #[link(name = "CoreFoundation", kind = "framework")]
+  extern "C" {
+    pub static __CFConstantStringClassReference: [u8; 0];
   }
 }
 
-#[repr(transparent)] pub struct CFStringRef(usize);
-#[repr(transparent)] pub struct CFMutableStringRef(usize);
+#[cfg(not(target_vendor = "apple"))]
+pub(crate) use crate::portable::string as ext;
+
+#[cfg(not(feature = "backend"))]
+use self::ext as ffi;
+
+#[cfg(feature = "backend")]
+use crate::backend::string as ffi;
+
+// Mutable strings share CFStringGetTypeID, so CFMutableStringRef cannot be a CFClass.
+cf_class! {
+  /// Strings can be shared with and sent to other threads, as nothing can mutate them.
+  pub unsafe class CFStringRef: CFTypeRef = CFStringGetTypeID where Send, Sync;
+
+  /// Mutable strings can be neither shared with nor sent to other threads. They are not a `Subtype` of
+  /// `CFStringRef`, which is both, so reading one as an immutable string goes through the unsafe `as_string`.
+  ///
+  /// ```compile_fail
+  /// fn assert_sync<T: Sync>() { }
+  ///
+  /// assert_sync::<hagane_core_foundation::CFMutableStringRef>();
+  /// ```
+  ///
+  /// ```compile_fail
+  /// fn assert_send<T: Send>() { }
+  ///
+  /// assert_send::<hagane_core_foundation::CFOwned<hagane_core_foundation::CFMutableStringRef>>();
+  /// ```
+  ///
+  /// ```compile_fail
+  /// fn assert_string<T: hagane_core_foundation::Subtype<hagane_core_foundation::CFStringRef>>() { }
+  ///
+  /// assert_string::<hagane_core_foundation::CFMutableStringRef>();
+  /// ```
+  pub unsafe class CFMutableStringRef: CFTypeRef;
+}
 
-unsafe impl Subtype<CFTypeRef> for CFStringRef {
-  unsafe fn upcast(&self) -> CFTypeRef {
-    return CFTypeRef(self.0);
+impl CFMutableStringRef {
+  /// Reads the string as an immutable `CFStringRef`.
+  ///
+  /// # Safety
+  ///
+  /// The string must not be mutated through any handle for as long as the returned reference, or anything retained
+  /// or sent to another thread through it, is in use.
+  pub unsafe fn as_string(&self) -> &CFStringRef {
+    return &*(self as *const CFMutableStringRef as *const CFStringRef);
   }
 }
 
-unsafe impl Subtype<CFStringRef> for CFStringRef {
-  unsafe fn upcast(&self) -> CFStringRef {
-    return CFStringRef(self.0);
-  }
+pub fn CFStringGetTypeID() -> CFTypeID {
+  return unsafe { ffi::CFStringGetTypeID() };
+}
+
+pub fn CFStringCreateWithCString(alloc: Option<&CFAllocatorRef>, cStr: &CStr, encoding: CFStringEncoding) -> Option<CFOwned<CFStringRef>> {
+  return unsafe { ffi::CFStringCreateWithCString(alloc.map(raw), cStr.as_ptr(), encoding).map(|string| CFOwned::from_create_rule(string)) };
+}
+
+pub fn CFStringCreateWithBytes(alloc: Option<&CFAllocatorRef>, bytes: &[u8], encoding: CFStringEncoding, isExternalRepresentation: bool) -> Option<CFOwned<CFStringRef>> {
+  return unsafe { ffi::CFStringCreateWithBytes(alloc.map(raw), bytes.as_ptr(), bytes.len() as CFIndex, encoding, isExternalRepresentation.into()).map(|string| CFOwned::from_create_rule(string)) };
+}
+
+pub fn CFStringGetLength<T: Subtype<CFStringRef>>(theString: &T) -> CFIndex {
+  return unsafe { ffi::CFStringGetLength(raw(theString)) };
+}
+
+pub fn CFStringGetCString<T: Subtype<CFStringRef>>(theString: &T, buffer: &mut [u8], encoding: CFStringEncoding) -> bool {
+  return unsafe { ffi::CFStringGetCString(raw(theString), buffer.as_mut_ptr() as *mut c_char, buffer.len() as CFIndex, encoding) }.into();
+}
+
+// Converts the characters in `range` into `buffer`, or only measures them for None, replacing those `encoding` cannot
+// represent with `lossByte`, or stopping at them for 0. Returns how many characters and bytes were converted.
+pub fn CFStringGetBytes<T: Subtype<CFStringRef>>(theString: &T, range: CFRange, encoding: CFStringEncoding, lossByte: u8, isExternalRepresentation: bool, buffer: Option<&mut [u8]>) -> Result<(CFIndex, CFIndex), CFRangeError> {
+  let range = range.validate(CFStringGetLength(theString))?;
+  let (buffer, maxBufLen) = buffer.map_or((ptr::null_mut(), 0), |buffer| (buffer.as_mut_ptr(), buffer.len() as CFIndex));
+  let mut usedBufLen = 0;
+  let converted = unsafe { ffi::CFStringGetBytes(raw(theString), range, encoding, lossByte, isExternalRepresentation.into(), buffer, maxBufLen, &mut usedBufLen) };
+
+  return Ok((converted, usedBufLen));
+}
+
+pub fn CFStringGetMaximumSizeForEncoding(length: CFIndex, encoding: CFStringEncoding) -> CFIndex {
+  return unsafe { ffi::CFStringGetMaximumSizeForEncoding(length, encoding) };
+}
+
+pub fn CFShowStr<T: Subtype<CFStringRef>>(string: &T) {
+  unsafe { ffi::CFShowStr(raw(string)) };
+}
+
+// The layout clang gives CFSTR literals: a class reference and flags marking the string as constant, followed by its
+// contents, which are NUL-terminated ASCII or UTF-16.
+#[cfg(target_vendor = "apple")]
+#[doc(hidden)] #[repr(C)] pub struct CFConstantString {
+  isa: Isa,
+  flags: u32,
+  bytes: *const u8,
+  length: CFIndex
+}
+
+#[cfg(not(target_vendor = "apple"))]
+#[doc(hidden)] #[repr(transparent)] pub struct CFConstantString(ext::CFString);
+
+#[cfg(all(target_vendor = "apple", not(feature = "dlopen")))]
+type Isa = *const c_void;
+
+// Without the framework linked in, the class is only known once it is loaded, and each string is given it then.
+#[cfg(all(target_vendor = "apple", feature = "dlopen"))]
+type Isa = std::cell::UnsafeCell<*const c_void>;
+
+#[cfg(all(target_vendor = "apple", not(feature = "dlopen")))]
+const fn isa() -> Isa {
+  return ptr::addr_of!(ext::__CFConstantStringClassReference) as *const c_void;
+}
+
+#[cfg(all(target_vendor = "apple", feature = "dlopen"))]
+const fn isa() -> Isa {
+  return std::cell::UnsafeCell::new(ptr::null());
 }
 
-unsafe impl Subtype<CFTypeRef> for CFMutableStringRef {
-  unsafe fn upcast(&self) -> CFTypeRef {
-    return CFTypeRef(self.0);
+unsafe impl Sync for CFConstantString { }
+
+impl CFConstantString {
+  #[cfg(target_vendor = "apple")]
+  pub const fn new(contents: &'static str, utf16: &'static [u16]) -> CFConstantString {
+    if contents.is_ascii() {
+      return CFConstantString { isa: isa(), flags: 0x07c8, bytes: contents.as_ptr(), length: contents.len() as CFIndex - 1 };
+    }
+
+    return CFConstantString { isa: isa(), flags: 0x07d0, bytes: utf16.as_ptr() as *const u8, length: utf16.len() as CFIndex - 1 };
+  }
+
+  #[cfg(not(target_vendor = "apple"))]
+  pub const fn new(contents: &'static str, _utf16: &'static [u16]) -> CFConstantString {
+    return CFConstantString(ext::constant(contents.as_ptr(), contents.len() - 1));
+  }
+
+  #[cfg(all(target_vendor = "apple", feature = "dlopen"))]
+  pub unsafe fn resolve(&'static self) {
+    if let Ok(class) = loader::CoreFoundation().and_then(|library| library.symbol("__CFConstantStringClassReference")) {
+      *self.isa.get() = class.as_ptr();
+    }
+  }
+
+  pub const fn string(&'static self) -> CFStringRef {
+    return CFStringRef(unsafe { NonNull::new_unchecked(self as *const CFConstantString as *mut c_void) });
   }
+
+  pub const fn utf16_len(contents: &str) -> usize {
+    let bytes = contents.as_bytes();
+    let mut length = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+      let (width, units) = utf8_width(bytes[i]);
+
+      length += units;
+      i += width;
+    }
+
+    return length;
+  }
+
+  pub const fn utf16<const N: usize>(contents: &str) -> [u16; N] {
+    let bytes = contents.as_bytes();
+    let mut utf16 = [0; N];
+    let mut length = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+      let (width, _) = utf8_width(bytes[i]);
+      let mut c = match width {
+        1 => bytes[i] as u32,
+        2 => bytes[i] as u32 & 0x1f,
+        3 => bytes[i] as u32 & 0x0f,
+        _ => bytes[i] as u32 & 0x07
+      };
+      let mut j = 1;
+
+      while j < width {
+        c = c << 6 | (bytes[i + j] as u32 & 0x3f);
+        j += 1;
+      }
+
+      if c >= 0x10000 {
+        utf16[length] = (0xd800 | (c - 0x10000) >> 10) as u16;
+        utf16[length + 1] = (0xdc00 | (c - 0x10000) & 0x3ff) as u16;
+        length += 2;
+      } else {
+        utf16[length] = c as u16;
+        length += 1;
+      }
+
+      i += width;
+    }
+
+    return utf16;
+  }
+}
+
+// The bytes taken by the UTF-8 sequence starting with `byte`, and the UTF-16 units it encodes to.
+const fn utf8_width(byte: u8) -> (usize, usize) {
+  return match byte {
+    0x00..=0x7f => (1, 1),
+    0xc0..=0xdf => (2, 1),
+    0xe0..=0xef => (3, 1),
+    _ => (4, 2)
+  };
 }
 
-unsafe impl Subtype<CFStringRef> for CFMutableStringRef {
-  unsafe fn upcast(&self) -> CFStringRef {
-    return CFStringRef(self.0);
+/// A CFStringRef for a string literal, laid out in static memory at compile time like CFSTR, so it is never created
+/// or released and can initialize other statics.
+///
+/// ```
+/// use hagane_core_foundation::*;
+///
+/// static KEY: &CFStringRef = cfstr!("key");
+///
+/// assert_eq!("key", String::from(KEY));
+/// ```
+#[macro_export]
+macro_rules! cfstr {
+  ($string:literal) => {{
+    const CONTENTS: &str = concat!($string, "\0");
+    const UTF16: [u16; $crate::CFConstantString::utf16_len(CONTENTS)] = $crate::CFConstantString::utf16(CONTENTS);
+
+    static STRING: $crate::CFConstantString = $crate::CFConstantString::new(CONTENTS, &UTF16);
+    static REF: $crate::CFStringRef = STRING.string();
+
+    $crate::__cfstr_resolve!(STRING);
+
+    &REF
+  }};
+}
+
+#[cfg(not(all(target_vendor = "apple", feature = "dlopen")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cfstr_resolve {
+  ($string:ident) => { };
+}
+
+#[cfg(all(target_vendor = "apple", feature = "dlopen"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cfstr_resolve {
+  ($string:ident) => {
+    #[used]
+    #[link_section = "__DATA,__mod_init_func"]
+    static RESOLVE: unsafe extern "C" fn() = {
+      unsafe extern "C" fn resolve() {
+        $string.resolve();
+      }
+
+      resolve
+    };
+  };
+}
+
+// Characters UTF-8 cannot hold, which only unpaired surrogates are, come out as '?' rather than failing the whole
+// conversion.
+impl<'a> From<&'a CFStringRef> for String {
+  fn from(string: &'a CFStringRef) -> String {
+    let range = CFRangeMake(0, CFStringGetLength(string));
+    let (_, size) = CFStringGetBytes(string, range, CFStringEncoding::kCFStringEncodingUTF8, b'?', false, None).unwrap_or_default();
+    let mut buffer = vec![0; size as usize];
+    let (_, used) = CFStringGetBytes(string, range, CFStringEncoding::kCFStringEncodingUTF8, b'?', false, Some(&mut buffer)).unwrap_or_default();
+
+    buffer.truncate(used as usize);
+
+    return String::from_utf8_lossy(&buffer).into_owned();
   }
 }
 
-unsafe impl Subtype<CFMutableStringRef> for CFMutableStringRef {
-  unsafe fn upcast(&self) -> CFMutableStringRef {
-    return CFMutableStringRef(self.0);
+#[cfg(test)]
+mod tests {
+  use crate::*;
+
+  #[test]
+  fn it_creates() {
+    let string = CFStringCreateWithCString(kCFAllocatorDefault, c"hagane", CFStringEncoding::kCFStringEncodingUTF8).unwrap();
+
+    assert_eq!(6, CFStringGetLength(&*string));
+    assert_eq!("hagane", String::from(&*string));
+  }
+
+  #[test]
+  fn it_makes_constant_strings() {
+    static KEY: &CFStringRef = cfstr!("key");
+
+    let string = CFStringCreateWithCString(kCFAllocatorDefault, c"key", CFStringEncoding::kCFStringEncodingUTF8).unwrap();
+    let pointers: Vec<_> = (0..3).map(|_| cfstr!("h\u{e9}llo \u{1f389}") as *const CFStringRef).collect();
+
+    assert_eq!(&*string, KEY);
+    assert_eq!(8, CFStringGetLength(cfstr!("h\u{e9}llo \u{1f389}")));
+    assert_eq!("h\u{e9}llo \u{1f389}", String::from(cfstr!("h\u{e9}llo \u{1f389}")));
+    assert!(pointers.iter().all(|&pointer| pointer == pointers[0]));
+
+    CFRelease(CFRetain(KEY));
+    assert_eq!("key", String::from(KEY));
+  }
+
+  #[test]
+  fn it_encodes_utf16() {
+    const CONTENTS: &str = "h\u{e9}\u{1f389}\0";
+
+    assert_eq!(5, CFConstantString::utf16_len(CONTENTS));
+    assert_eq!(CONTENTS.encode_utf16().collect::<Vec<_>>(), CFConstantString::utf16::<5>(CONTENTS));
+  }
+
+  #[test]
+  fn it_converts_embedded_nuls() {
+    let string = CFStringCreateWithBytes(kCFAllocatorDefault, b"a\0b", CFStringEncoding::kCFStringEncodingUTF8, false).unwrap();
+
+    assert_eq!("a\0b", String::from(&*string));
+    assert_eq!("a\0b", string.to_string());
+  }
+
+  #[test]
+  fn it_gets_bytes() {
+    let string = CFStringCreateWithBytes(kCFAllocatorDefault, "h\u{e9}llo".as_bytes(), CFStringEncoding::kCFStringEncodingUTF8, false).unwrap();
+    let mut buffer = [0; 8];
+
+    assert_eq!(Ok((5, 5)), CFStringGetBytes(&*string, CFRangeMake(0, 5), CFStringEncoding::kCFStringEncodingASCII, b'?', false, Some(&mut buffer)));
+    assert_eq!(b"h?llo", &buffer[..5]);
+    assert_eq!(Ok((1, 1)), CFStringGetBytes(&*string, CFRangeMake(0, 5), CFStringEncoding::kCFStringEncodingASCII, 0, false, None));
+    assert_eq!(Ok((2, 3)), CFStringGetBytes(&*string, CFRangeMake(0, 2), CFStringEncoding::kCFStringEncodingUTF8, 0, false, None));
+    assert!(CFStringGetBytes(&*string, CFRangeMake(3, 3), CFStringEncoding::kCFStringEncodingUTF8, 0, false, None).is_err());
+  }
+
+  #[test]
+  fn it_fails_to_convert() {
+    assert!(CFStringCreateWithBytes(kCFAllocatorDefault, b"\xff\xfe", CFStringEncoding::kCFStringEncodingUTF8, false).is_none());
+    assert_eq!(mem::size_of::<*const c_void>(), mem::size_of::<Option<CFOwned<CFStringRef>>>());
   }
 }
@@ -0,0 +1,187 @@
+// Records every reference taken or given up through CFOwned, with its backtrace and the object's type ID, so that
+// leaked and over-released objects can be traced back to where their references came from.
+
+use crate::*;
+
+use std::backtrace::Backtrace;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread::{self, ThreadId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+  Create,
+  Retain,
+  Release,
+  Autorelease,
+  IntoRaw
+}
+
+struct Record {
+  event: Event,
+  thread: ThreadId,
+  backtrace: Backtrace
+}
+
+impl fmt::Display for Record {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return write!(f, "{:?} on {:?}\n{}", self.event, self.thread, self.backtrace);
+  }
+}
+
+struct Object {
+  type_id: CFTypeID,
+  references: usize,
+  thread: ThreadId,
+  sequence: u64,
+  history: Vec<Record>
+}
+
+// A reference count that no longer matches the object's retain count, found while recording `record`.
+struct Imbalance {
+  address: usize,
+  type_id: CFTypeID,
+  references: usize,
+  retain_count: CFIndex,
+  sequence: u64,
+  record: Record
+}
+
+struct Tracker {
+  sequence: u64,
+  objects: BTreeMap<usize, Object>,
+  imbalances: Vec<Imbalance>
+}
+
+static TRACKER: Mutex<Tracker> = Mutex::new(Tracker { sequence: 0, objects: BTreeMap::new(), imbalances: Vec::new() });
+
+// A failed assertion may have panicked while the tracker was locked, which leaves it consistent all the same.
+fn lock() -> MutexGuard<'static, Tracker> {
+  return TRACKER.lock().unwrap_or_else(PoisonError::into_inner);
+}
+
+// Tracked references each stand for a retain, so there can never be more of them than the retain count.
+pub(crate) fn record(event: Event, cf: &CFTypeRef) {
+  let address = cf.0.as_ptr() as usize;
+  let type_id = CFGetTypeID(cf);
+  let retain_count = CFGetRetainCount(cf);
+  let record = Record { event, thread: thread::current().id(), backtrace: Backtrace::force_capture() };
+
+  let mut tracker = lock();
+  let tracker = &mut *tracker;
+
+  tracker.sequence += 1;
+
+  let sequence = tracker.sequence;
+  let object = tracker.objects.entry(address).or_insert_with(|| Object {
+    type_id,
+    references: 0,
+    thread: record.thread,
+    sequence,
+    history: Vec::new()
+  });
+
+  let references = match event {
+    Event::Create | Event::Retain => object.references + 1,
+    Event::Release | Event::Autorelease | Event::IntoRaw => object.references
+  };
+
+  if references == 0 || references as CFIndex > retain_count {
+    tracker.imbalances.push(Imbalance { address, type_id, references, retain_count, sequence, record });
+  } else {
+    object.history.push(record);
+  }
+
+  object.references = match event {
+    Event::Create | Event::Retain => references,
+    Event::Release | Event::Autorelease | Event::IntoRaw => references.saturating_sub(1)
+  };
+
+  if object.references == 0 {
+    tracker.objects.remove(&address);
+  }
+}
+
+fn class_name(type_id: CFTypeID) -> String {
+  return CFCopyTypeIDDescription(type_id).map_or_else(|| format!("type ID {}", type_id.0), |description| String::from(&*description));
+}
+
+// Panics if an object created by `f` on this thread is still referenced through CFOwned once it returns, or if any
+// of the references it took or gave up did not match the object's retain count.
+pub fn assert_no_leaks<R>(f: impl FnOnce() -> R) -> R {
+  let start = lock().sequence;
+  let result = f();
+  let thread = thread::current().id();
+
+  // Reports are put together without holding the lock, since looking up class names creates more objects.
+  let mut leaks = Vec::new();
+  let mut imbalances = Vec::new();
+
+  {
+    let tracker = lock();
+
+    for (address, object) in tracker.objects.iter() {
+      if object.thread == thread && object.sequence > start {
+        let history = object.history.iter().map(|record| record.to_string()).collect::<Vec<_>>().join("\n");
+
+        leaks.push((object.type_id, *address, object.references, history));
+      }
+    }
+
+    for imbalance in tracker.imbalances.iter() {
+      if imbalance.record.thread == thread && imbalance.sequence > start {
+        imbalances.push((imbalance.type_id, imbalance.address, imbalance.references, imbalance.retain_count, imbalance.record.to_string()));
+      }
+    }
+  }
+
+  if leaks.is_empty() && imbalances.is_empty() {
+    return result;
+  }
+
+  let mut report = String::new();
+
+  for (type_id, address, references, history) in leaks {
+    let _ = write!(report, "\n{} {:#x} leaked with {} outstanding references:\n{}", class_name(type_id), address, references, history);
+  }
+
+  for (type_id, address, references, retain_count, record) in imbalances {
+    let _ = write!(report, "\n{} {:#x} over-released, with {} references tracked against a retain count of {}:\n{}", class_name(type_id), address, references, retain_count, record);
+  }
+
+  panic!("CF objects outlived or were released past their scope:{}", report);
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::*;
+
+  use std::panic::{self, AssertUnwindSafe};
+
+  fn create() -> CFOwned<CFStringRef> {
+    return CFStringCreateWithCString(kCFAllocatorDefault, c"hagane-core-foundation tracker", CFStringEncoding::kCFStringEncodingUTF8).unwrap();
+  }
+
+  #[test]
+  fn it_finds_leaks() {
+    tracker::assert_no_leaks(|| {
+      let string = create();
+      let copy = string.clone();
+
+      unsafe { CFAutorelease(copy) };
+    });
+
+    assert!(panic::catch_unwind(|| tracker::assert_no_leaks(|| mem::forget(create()))).is_err());
+  }
+
+  #[test]
+  fn it_finds_over_releases() {
+    let string = create();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| tracker::assert_no_leaks(|| {
+      unsafe { CFOwned::from_create_rule(ptr::read(&*string)) }.into_raw();
+    })));
+
+    assert!(result.is_err());
+  }
+}
@@ -1,3 +1,81 @@
+#![allow(clippy::needless_return)]
+
+/// # Safety
+///
+/// Implementors must share their representation with `T`, so that `upcast` only reinterprets the handle.
 pub unsafe trait Subtype<T> : Sized {
-  unsafe fn upcast(&self) -> T;
+  fn upcast(&self) -> &T {
+    return unsafe { &*(self as *const Self as *const T) };
+  }
+}
+
+/// Declares CoreFoundation-style handle types and their place in the class hierarchy.
+///
+/// # Safety
+///
+/// Every `class` is declared `unsafe`, as its handle is taken for a valid object of each ancestor it lists, which
+/// the macro has no way to check.
+///
+/// Each `class` lists every ancestor, nearest first, and optionally the function returning its type ID. The
+/// handle wraps a non-null object pointer, gets `Subtype` impls for itself and all of its ancestors, and, given a
+/// type ID function, a `CFClass` impl for the `CFClass` and `CFTypeID` in scope where the macro is used. Its
+/// `Debug`, `Display`, `PartialEq`, `Eq` and `Hash` impls likewise go through the `CFTypeClass` in scope.
+///
+/// Handles are neither `Send` nor `Sync` unless listed after `where`, which should only be done for classes
+/// whose instances are immutable. The `unsafe` vouches for those too, including every subclass that can be
+/// upcast to the class, since a handle reached that way is as free to cross threads as any other. A mutable
+/// subclass must therefore leave such a class out of its ancestors.
+///
+/// ```ignore
+/// cf_class! {
+///   pub unsafe class CFStringRef: CFTypeRef = CFStringGetTypeID where Send, Sync;
+///   pub unsafe class CFMutableStringRef: CFTypeRef;
+/// }
+/// ```
+#[macro_export]
+macro_rules! cf_class {
+  ($($(#[$attr:meta])* $vis:vis unsafe class $name:ident $(: $($parent:ident),+)? $(= $type_id:path)? $(where $($marker:ident),+)?;)*) => {
+    $(
+      $(#[$attr])*
+      #[repr(transparent)] $vis struct $name(pub(crate) ::std::ptr::NonNull<::std::os::raw::c_void>);
+
+      unsafe impl $crate::Subtype<$name> for $name { }
+      $($(unsafe impl $crate::Subtype<$parent> for $name { })+)?
+      $($(unsafe impl $marker for $name { })+)?
+
+      impl ::std::fmt::Debug for $name {
+        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+          return CFTypeClass::fmt_debug(self, f);
+        }
+      }
+
+      impl ::std::fmt::Display for $name {
+        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+          return CFTypeClass::fmt_display(self, f);
+        }
+      }
+
+      impl ::std::cmp::PartialEq for $name {
+        fn eq(&self, other: &$name) -> bool {
+          return CFTypeClass::equal(self, other);
+        }
+      }
+
+      impl ::std::cmp::Eq for $name { }
+
+      impl ::std::hash::Hash for $name {
+        fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
+          ::std::hash::Hash::hash(&CFTypeClass::hash(self), state);
+        }
+      }
+
+      $(
+        unsafe impl CFClass for $name {
+          fn type_id() -> CFTypeID {
+            return $type_id();
+          }
+        }
+      )?
+    )*
+  };
 }
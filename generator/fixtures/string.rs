@@ -0,0 +1,122 @@
+use crate::*;
+
+#[cfg(target_vendor = "apple")]
+pub(crate) mod ext {
+  use crate::*;
+
+  cf_extern! {
+    pub fn CFStringGetTypeID() -> CFTypeID;
+    pub fn CFStringCreateWithCString(alloc: Option<CFAllocatorRef>, cStr: *const c_char, encoding: CFStringEncoding) -> Option<CFStringRef>;
+    pub fn CFStringCreateWithBytes(alloc: Option<CFAllocatorRef>, bytes: *const UInt8, numBytes: CFIndex, encoding: CFStringEncoding, isExternalRepresentation: Boolean) -> Option<CFStringRef>;
+    pub fn CFStringCreateWithSubstring(alloc: Option<CFAllocatorRef>, str: CFStringRef, range: CFRange) -> Option<CFStringRef>;
+    // CFStringRef CFStringCreateWithFormat(CFAllocatorRef alloc, CFDictionaryRef formatOptions, CFStringRef format, ...);
+    // CFStringRef CFStringCreateWithFormatAndArguments(CFAllocatorRef alloc, CFDictionaryRef formatOptions, CFStringRef format, va_list arguments);
+    pub fn CFStringCreateMutableCopy(alloc: Option<CFAllocatorRef>, maxLength: CFIndex, theString: CFStringRef) -> Option<CFMutableStringRef>;
+    pub fn CFStringGetLength(theString: CFStringRef) -> CFIndex;
+    pub fn CFStringGetCString(theString: CFStringRef, buffer: *mut c_char, bufferSize: CFIndex, encoding: CFStringEncoding) -> Boolean;
+    pub fn CFStringGetCStringPtr(theString: CFStringRef, encoding: CFStringEncoding) -> *const c_char;
+    pub fn CFStringGetCharacters(theString: CFStringRef, range: CFRange, buffer: *mut UniChar);
+    pub fn CFStringGetMaximumSizeForEncoding(length: CFIndex, encoding: CFStringEncoding) -> CFIndex;
+    pub fn CFStringCompareWithOptionsAndLocale(theString1: CFStringRef, theString2: CFStringRef, rangeToCompare: CFRange, compareOptions: CFStringCompareFlags, locale: CFLocaleRef) -> CFComparisonResult;
+    pub fn CFStringHasPrefix(theString: CFStringRef, prefix: CFStringRef) -> Boolean;
+    pub fn CFStringGetNameOfEncoding(encoding: CFStringEncoding) -> Option<CFStringRef>;
+    pub fn CFStringConvertEncodingToNSStringEncoding(encoding: CFStringEncoding) -> c_ulong;
+    pub fn CFStringGetListOfAvailableEncodings() -> *const CFStringEncoding;
+    pub fn CFStringAppendCString(theString: CFMutableStringRef, cStr: *const c_char, encoding: CFStringEncoding);
+    pub fn CFStringNormalize(theString: CFMutableStringRef, theForm: CFStringNormalizationForm);
+    pub fn CFStringFold(theString: CFMutableStringRef, theFlags: CFStringCompareFlags, theLocale: CFLocaleRef);
+    pub static kCFStringTransformToLatin: CFStringRef;
+    pub static kCFStringTransformStripDiacritics: CFStringRef;
+    pub fn CFShowStr(str: CFStringRef);
+    pub fn __CFStringMakeConstantString(cStr: *const c_char) -> Option<CFStringRef>;
+  }
+}
+
+#[cfg(not(target_vendor = "apple"))]
+pub(crate) use crate::portable::string as ext;
+
+#[cfg(not(feature = "backend"))]
+use self::ext as ffi;
+
+#[cfg(feature = "backend")]
+use crate::backend::string as ffi;
+
+pub fn CFStringGetTypeID() -> CFTypeID {
+  return unsafe { ffi::CFStringGetTypeID() };
+}
+
+pub fn CFStringCreateWithCString(alloc: Option<&CFAllocatorRef>, cStr: &CStr, encoding: CFStringEncoding) -> Option<CFOwned<CFStringRef>> {
+  return unsafe { ffi::CFStringCreateWithCString(alloc.map(raw), cStr.as_ptr(), encoding).map(|string| CFOwned::from_create_rule(string)) };
+}
+
+pub unsafe fn CFStringCreateWithBytes(alloc: Option<&CFAllocatorRef>, bytes: *const UInt8, numBytes: CFIndex, encoding: CFStringEncoding, isExternalRepresentation: bool) -> Option<CFOwned<CFStringRef>> {
+  return ffi::CFStringCreateWithBytes(alloc.map(raw), bytes, numBytes, encoding, isExternalRepresentation.into()).map(|string| CFOwned::from_create_rule(string));
+}
+
+pub fn CFStringCreateWithSubstring<T: Subtype<CFStringRef>>(alloc: Option<&CFAllocatorRef>, str: &T, range: CFRange) -> Option<CFOwned<CFStringRef>> {
+  return unsafe { ffi::CFStringCreateWithSubstring(alloc.map(raw), raw(str), range).map(|string| CFOwned::from_create_rule(string)) };
+}
+
+pub fn CFStringCreateMutableCopy<T: Subtype<CFStringRef>>(alloc: Option<&CFAllocatorRef>, maxLength: CFIndex, theString: &T) -> Option<CFOwned<CFMutableStringRef>> {
+  return unsafe { ffi::CFStringCreateMutableCopy(alloc.map(raw), maxLength, raw(theString)).map(|mutableString| CFOwned::from_create_rule(mutableString)) };
+}
+
+pub fn CFStringGetLength<T: Subtype<CFStringRef>>(theString: &T) -> CFIndex {
+  return unsafe { ffi::CFStringGetLength(raw(theString)) };
+}
+
+pub unsafe fn CFStringGetCString<T: Subtype<CFStringRef>>(theString: &T, buffer: *mut c_char, bufferSize: CFIndex, encoding: CFStringEncoding) -> bool {
+  return ffi::CFStringGetCString(raw(theString), buffer, bufferSize, encoding).into();
+}
+
+pub fn CFStringGetCStringPtr<T: Subtype<CFStringRef>>(theString: &T, encoding: CFStringEncoding) -> *const c_char {
+  return unsafe { ffi::CFStringGetCStringPtr(raw(theString), encoding) };
+}
+
+pub unsafe fn CFStringGetCharacters<T: Subtype<CFStringRef>>(theString: &T, range: CFRange, buffer: *mut UniChar) {
+  ffi::CFStringGetCharacters(raw(theString), range, buffer);
+}
+
+pub fn CFStringGetMaximumSizeForEncoding(length: CFIndex, encoding: CFStringEncoding) -> CFIndex {
+  return unsafe { ffi::CFStringGetMaximumSizeForEncoding(length, encoding) };
+}
+
+pub fn CFStringCompareWithOptionsAndLocale<T1: Subtype<CFStringRef>, T2: Subtype<CFStringRef>, T3: Subtype<CFLocaleRef>>(theString1: &T1, theString2: &T2, rangeToCompare: CFRange, compareOptions: CFStringCompareFlags, locale: &T3) -> CFComparisonResult {
+  return unsafe { ffi::CFStringCompareWithOptionsAndLocale(raw(theString1), raw(theString2), rangeToCompare, compareOptions, raw(locale)) };
+}
+
+pub fn CFStringHasPrefix<T1: Subtype<CFStringRef>, T2: Subtype<CFStringRef>>(theString: &T1, prefix: &T2) -> bool {
+  return unsafe { ffi::CFStringHasPrefix(raw(theString), raw(prefix)) }.into();
+}
+
+pub fn CFStringGetNameOfEncoding(encoding: CFStringEncoding) -> Option<CFRef<'static, CFStringRef>> {
+  return unsafe { ffi::CFStringGetNameOfEncoding(encoding).map(|string| CFRef::from_get_rule(string)) };
+}
+
+pub fn CFStringConvertEncodingToNSStringEncoding(encoding: CFStringEncoding) -> c_ulong {
+  return unsafe { ffi::CFStringConvertEncodingToNSStringEncoding(encoding) };
+}
+
+pub fn CFStringGetListOfAvailableEncodings() -> *const CFStringEncoding {
+  return unsafe { ffi::CFStringGetListOfAvailableEncodings() };
+}
+
+pub fn CFStringAppendCString<T: Subtype<CFMutableStringRef>>(theString: &T, cStr: &CStr, encoding: CFStringEncoding) {
+  unsafe { ffi::CFStringAppendCString(raw(theString), cStr.as_ptr(), encoding) };
+}
+
+pub fn CFStringNormalize<T: Subtype<CFMutableStringRef>>(theString: &T, theForm: CFStringNormalizationForm) {
+  unsafe { ffi::CFStringNormalize(raw(theString), theForm) };
+}
+
+pub fn CFStringFold<T1: Subtype<CFMutableStringRef>, T2: Subtype<CFLocaleRef>>(theString: &T1, theFlags: CFStringCompareFlags, theLocale: &T2) {
+  unsafe { ffi::CFStringFold(raw(theString), theFlags, raw(theLocale)) };
+}
+
+pub static kCFStringTransformToLatin: CFConstant<CFStringRef> = cf_constant!(ext::kCFStringTransformToLatin);
+
+pub static kCFStringTransformStripDiacritics: CFConstant<CFStringRef> = cf_constant!(ext::kCFStringTransformStripDiacritics);
+
+pub fn CFShowStr<T: Subtype<CFStringRef>>(str: &T) {
+  unsafe { ffi::CFShowStr(raw(str)) };
+}
@@ -0,0 +1,26 @@
+cf_extern! {
+  pub fn CFStringGetTypeID() -> CFTypeID;
+  pub fn CFStringCreateWithCString(alloc: Option<CFAllocatorRef>, cStr: *const c_char, encoding: CFStringEncoding) -> Option<CFStringRef>;
+  pub fn CFStringCreateWithBytes(alloc: Option<CFAllocatorRef>, bytes: *const UInt8, numBytes: CFIndex, encoding: CFStringEncoding, isExternalRepresentation: Boolean) -> Option<CFStringRef>;
+  pub fn CFStringCreateWithSubstring(alloc: Option<CFAllocatorRef>, str: CFStringRef, range: CFRange) -> Option<CFStringRef>;
+  // CFStringRef CFStringCreateWithFormat(CFAllocatorRef alloc, CFDictionaryRef formatOptions, CFStringRef format, ...);
+  // CFStringRef CFStringCreateWithFormatAndArguments(CFAllocatorRef alloc, CFDictionaryRef formatOptions, CFStringRef format, va_list arguments);
+  pub fn CFStringCreateMutableCopy(alloc: Option<CFAllocatorRef>, maxLength: CFIndex, theString: CFStringRef) -> Option<CFMutableStringRef>;
+  pub fn CFStringGetLength(theString: CFStringRef) -> CFIndex;
+  pub fn CFStringGetCString(theString: CFStringRef, buffer: *mut c_char, bufferSize: CFIndex, encoding: CFStringEncoding) -> Boolean;
+  pub fn CFStringGetCStringPtr(theString: CFStringRef, encoding: CFStringEncoding) -> *const c_char;
+  pub fn CFStringGetCharacters(theString: CFStringRef, range: CFRange, buffer: *mut UniChar);
+  pub fn CFStringGetMaximumSizeForEncoding(length: CFIndex, encoding: CFStringEncoding) -> CFIndex;
+  pub fn CFStringCompareWithOptionsAndLocale(theString1: CFStringRef, theString2: CFStringRef, rangeToCompare: CFRange, compareOptions: CFStringCompareFlags, locale: CFLocaleRef) -> CFComparisonResult;
+  pub fn CFStringHasPrefix(theString: CFStringRef, prefix: CFStringRef) -> Boolean;
+  pub fn CFStringGetNameOfEncoding(encoding: CFStringEncoding) -> Option<CFStringRef>;
+  pub fn CFStringConvertEncodingToNSStringEncoding(encoding: CFStringEncoding) -> c_ulong;
+  pub fn CFStringGetListOfAvailableEncodings() -> *const CFStringEncoding;
+  pub fn CFStringAppendCString(theString: CFMutableStringRef, cStr: *const c_char, encoding: CFStringEncoding);
+  pub fn CFStringNormalize(theString: CFMutableStringRef, theForm: CFStringNormalizationForm);
+  pub fn CFStringFold(theString: CFMutableStringRef, theFlags: CFStringCompareFlags, theLocale: CFLocaleRef);
+  pub static kCFStringTransformToLatin: CFStringRef;
+  pub static kCFStringTransformStripDiacritics: CFStringRef;
+  pub fn CFShowStr(str: CFStringRef);
+  pub fn __CFStringMakeConstantString(cStr: *const c_char) -> Option<CFStringRef>;
+}
@@ -0,0 +1,560 @@
+#![allow(non_upper_case_globals, clippy::needless_return)]
+
+// Reads the C declarations in a CoreFoundation header and writes the `ext` block and wrappers for them in the style
+// of hagane-core-foundation. The output is a first pass to be reviewed by hand: wrappers taking raw pointers are left
+// unsafe, and declarations it cannot bind, such as variadic functions, are kept as comments in the `ext` block. The
+// functions also need listing in backend.rs for the `backend` feature to route them.
+
+use std::fmt::Write;
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Type {
+  Void,
+  Named(String),
+  Pointer { constant: bool, pointee: Box<Type> }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Parameter {
+  pub name: String,
+  pub ty: Type
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Declaration {
+  Function { name: String, result: Type, parameters: Vec<Parameter> },
+  Constant { name: String, ty: Type },
+  // A declaration that cannot be bound, as it appears in the header.
+  Unsupported(String)
+}
+
+const kKeywords: &[&str] = &[
+  "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn", "for",
+  "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "static", "struct", "super",
+  "trait", "true", "type", "unsafe", "use", "where", "while", "yield"
+];
+
+// Storage classes, nullability and other qualifiers that do not change how a declaration is bound.
+const kIgnoredWords: &[&str] = &[
+  "extern", "static", "inline", "__inline", "restrict", "__restrict", "_Nullable", "_Nonnull", "_Null_unspecified",
+  "__nullable", "__nonnull", "__null_unspecified", "volatile"
+];
+
+fn strip(header: &str) -> String {
+  let mut text = String::with_capacity(header.len());
+  let mut chars = header.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    match (c, chars.peek()) {
+      ('/', Some('*')) => {
+        chars.next();
+
+        let mut previous = ' ';
+
+        for c in chars.by_ref() {
+          if previous == '*' && c == '/' {
+            break;
+          }
+
+          previous = c;
+        }
+
+        text.push(' ');
+      },
+      ('/', Some('/')) => {
+        while chars.peek().is_some_and(|&c| c != '\n') {
+          chars.next();
+        }
+      },
+      _ => text.push(c)
+    }
+  }
+
+  let mut result = String::with_capacity(text.len());
+  let mut directive = false;
+
+  for line in text.lines() {
+    if directive || line.trim_start().starts_with('#') {
+      directive = line.trim_end().ends_with('\\');
+      continue;
+    }
+
+    result.push_str(line);
+    result.push('\n');
+  }
+
+  return result;
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+  let mut tokens = Vec::new();
+  let mut chars = text.chars().peekable();
+
+  while let Some(&c) = chars.peek() {
+    if c.is_whitespace() {
+      chars.next();
+    } else if c.is_ascii_alphanumeric() || c == '_' {
+      let mut token = String::new();
+
+      while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric() || **c == '_') {
+        token.push(c);
+        chars.next();
+      }
+
+      tokens.push(token);
+    } else if c == '.' {
+      let mut token = String::new();
+
+      while let Some(&c) = chars.peek().filter(|c| **c == '.') {
+        token.push(c);
+        chars.next();
+      }
+
+      tokens.push(token);
+    } else {
+      tokens.push(c.to_string());
+      chars.next();
+    }
+  }
+
+  return tokens;
+}
+
+// Macros such as CF_EXPORT, CF_RETURNS_RETAINED and API_AVAILABLE(macos(10.5)) are all capitals with an underscore.
+fn is_macro(token: &str) -> bool {
+  return token.contains('_') && token.chars().any(|c| c.is_ascii_uppercase()) && token.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+}
+
+fn closing(tokens: &[String], open: usize) -> usize {
+  let (left, right) = if tokens[open] == "{" { ("{", "}") } else { ("(", ")") };
+  let mut depth = 0;
+
+  for (i, token) in tokens.iter().enumerate().skip(open) {
+    if token == left {
+      depth += 1;
+    } else if token == right {
+      depth -= 1;
+
+      if depth == 0 {
+        return i;
+      }
+    }
+  }
+
+  return tokens.len() - 1;
+}
+
+fn statements(tokens: &[String]) -> Vec<Vec<String>> {
+  let mut statements = Vec::new();
+  let mut statement = Vec::new();
+  let mut i = 0;
+
+  while i < tokens.len() {
+    let token = &tokens[i];
+
+    if token == "{" {
+      let end = closing(tokens, i);
+
+      // Inline function bodies end the declaration without a semicolon.
+      if statement.last().is_some_and(|last: &String| last == ")") {
+        statement.clear();
+      } else {
+        statement.extend_from_slice(&tokens[i..=end]);
+      }
+
+      i = end + 1;
+      continue;
+    }
+
+    if token == ";" {
+      if !statement.is_empty() {
+        statements.push(std::mem::take(&mut statement));
+      }
+    } else if is_macro(token) {
+      if tokens.get(i + 1).is_some_and(|next| next == "(") {
+        i = closing(tokens, i + 1);
+      }
+    } else if !kIgnoredWords.contains(&token.as_str()) {
+      statement.push(token.clone());
+    }
+
+    i += 1;
+  }
+
+  return statements;
+}
+
+fn render(tokens: &[String]) -> String {
+  let mut text = String::new();
+
+  for (i, token) in tokens.iter().enumerate() {
+    let previous = if i > 0 { tokens[i - 1].as_str() } else { "(" };
+    let call = token == "(" && previous != "void" && (previous == ")" || previous.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_'));
+    let attached = call || matches!(token.as_str(), "," | ")" | "[" | "]") || matches!(previous, "(" | "[" | "*" | "^");
+
+    if !attached {
+      text.push(' ');
+    }
+
+    text.push_str(token);
+  }
+
+  return format!("{};", text.trim_start());
+}
+
+fn primitive(words: &[&str]) -> Option<&'static str> {
+  return Some(match words {
+    ["char"] => "c_char",
+    ["signed", "char"] => "i8",
+    ["unsigned", "char"] => "u8",
+    ["short"] | ["short", "int"] | ["signed", "short"] => "i16",
+    ["unsigned", "short"] | ["unsigned", "short", "int"] => "u16",
+    ["int"] | ["signed"] | ["signed", "int"] => "c_int",
+    ["unsigned"] | ["unsigned", "int"] => "c_uint",
+    ["long"] | ["long", "int"] | ["signed", "long"] => "c_long",
+    ["unsigned", "long"] | ["unsigned", "long", "int"] => "c_ulong",
+    ["long", "long"] | ["signed", "long", "long"] => "i64",
+    ["unsigned", "long", "long"] => "u64",
+    ["float"] => "f32",
+    ["double"] => "f64",
+    ["bool"] | ["_Bool"] => "bool",
+    ["size_t"] | ["uintptr_t"] => "usize",
+    ["ssize_t"] | ["intptr_t"] => "isize",
+    ["int8_t"] => "i8",
+    ["int16_t"] => "i16",
+    ["int32_t"] => "i32",
+    ["int64_t"] => "i64",
+    ["uint8_t"] => "u8",
+    ["uint16_t"] => "u16",
+    ["uint32_t"] => "u32",
+    ["uint64_t"] => "u64",
+    _ => return None
+  });
+}
+
+// Reads a type written as qualifiers, base words and stars. A const before the first star applies to what it points
+// to, and one after a star to the pointer itself, which Rust does not distinguish.
+fn parse_type(tokens: &[&str]) -> Option<Type> {
+  let stars = tokens.iter().position(|&token| token == "*").unwrap_or(tokens.len());
+  let words: Vec<&str> = tokens[..stars].iter().copied().filter(|&token| token != "const").collect();
+  let mut constant = tokens[..stars].contains(&"const");
+
+  let mut ty = match (words.as_slice(), primitive(&words)) {
+    (["void"], _) => Type::Void,
+    (_, Some(primitive)) => Type::Named(primitive.to_string()),
+    ([word], None) => Type::Named(word.to_string()),
+    _ => return None
+  };
+
+  for (i, &token) in tokens.iter().enumerate().skip(stars) {
+    match token {
+      "*" => {
+        ty = Type::Pointer { constant, pointee: Box::new(ty) };
+        constant = tokens.get(i + 1) == Some(&"const");
+      },
+      "const" => { },
+      _ => return None
+    }
+  }
+
+  return Some(ty);
+}
+
+fn parse_parameter(tokens: &[String], index: usize) -> Option<Parameter> {
+  let mut tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+  let mut array = false;
+
+  if let Some(open) = tokens.iter().position(|&token| token == "[") {
+    tokens.truncate(open);
+    array = true;
+  }
+
+  // An unnamed parameter ends with its type, which is either a star or a word C gives meaning to.
+  let named = tokens.len() > 1 && tokens.last().is_some_and(|last| *last != "*" && *last != "const" && primitive(&[last]).is_none() && !matches!(*last, "void" | "signed" | "unsigned" | "long" | "short"));
+
+  let name = if named { tokens.pop()?.to_string() } else { format!("arg{}", index) };
+  let mut ty = parse_type(&tokens)?;
+
+  if array {
+    ty = Type::Pointer { constant: tokens.contains(&"const"), pointee: Box::new(ty) };
+  }
+
+  return Some(Parameter { name, ty });
+}
+
+fn parse_statement(tokens: Vec<String>) -> Option<Declaration> {
+  if matches!(tokens[0].as_str(), "typedef" | "struct" | "enum" | "union") || tokens.iter().any(|token| token == "{") {
+    return None;
+  }
+
+  let unsupported = Some(Declaration::Unsupported(render(&tokens)));
+
+  let Some(open) = tokens.iter().position(|token| token == "(") else {
+    let (name, ty) = tokens.split_last()?;
+    let ty: Vec<&str> = ty.iter().map(String::as_str).collect();
+
+    return match parse_type(&ty) {
+      Some(ty) if ty != Type::Void => Some(Declaration::Constant { name: name.clone(), ty }),
+      _ => unsupported
+    };
+  };
+
+  let close = closing(&tokens, open);
+
+  if open == 0 || close + 1 != tokens.len() {
+    return unsupported;
+  }
+
+  let name = tokens[open - 1].clone();
+  let result: Vec<&str> = tokens[..open - 1].iter().map(String::as_str).collect();
+  let Some(result) = parse_type(&result) else {
+    return unsupported;
+  };
+
+  let inner = &tokens[open + 1..close];
+  let mut parameters = Vec::new();
+
+  if !(inner.is_empty() || inner == ["void"]) {
+    for (index, parameter) in inner.split(|token| token == ",").enumerate() {
+      if parameter.iter().any(|token| matches!(token.as_str(), "(" | "..." | "va_list")) {
+        return unsupported;
+      }
+
+      match parse_parameter(parameter, index) {
+        Some(parameter) => parameters.push(parameter),
+        None => return unsupported
+      }
+    }
+  }
+
+  return Some(Declaration::Function { name, result, parameters });
+}
+
+pub fn parse(header: &str) -> Vec<Declaration> {
+  return statements(&tokenize(&strip(header))).into_iter().filter_map(parse_statement).collect();
+}
+
+fn is_handle(ty: &Type) -> bool {
+  return matches!(ty, Type::Named(name) if name.ends_with("Ref"));
+}
+
+fn is_named(ty: &Type, expected: &str) -> bool {
+  return matches!(ty, Type::Named(name) if name == expected);
+}
+
+fn is_c_string(ty: &Type) -> bool {
+  return matches!(ty, Type::Pointer { constant: true, pointee } if is_named(pointee, "c_char"));
+}
+
+fn identifier(name: &str) -> String {
+  return if kKeywords.contains(&name) { format!("r#{}", name) } else { name.to_string() };
+}
+
+fn rust_type(ty: &Type) -> String {
+  return match ty {
+    Type::Void => String::from("c_void"),
+    Type::Named(name) => name.clone(),
+    Type::Pointer { constant: true, pointee } => format!("*const {}", rust_type(pointee)),
+    Type::Pointer { constant: false, pointee } => format!("*mut {}", rust_type(pointee))
+  };
+}
+
+// CoreFoundation takes NULL for the default allocator, and returns NULL from functions that fail.
+fn ext_parameter(ty: &Type) -> String {
+  return if is_named(ty, "CFAllocatorRef") { String::from("Option<CFAllocatorRef>") } else { rust_type(ty) };
+}
+
+fn ext_result(ty: &Type) -> String {
+  return match ty {
+    Type::Void => String::new(),
+    ty if is_handle(ty) => format!(" -> Option<{}>", rust_type(ty)),
+    ty => format!(" -> {}", rust_type(ty))
+  };
+}
+
+// The name used for the object in closures, such as `string` for CFStringRef.
+fn variable(handle: &str) -> String {
+  let stem = handle.strip_prefix("CF").unwrap_or(handle);
+  let stem = stem.strip_suffix("Ref").unwrap_or(stem);
+  let mut chars = stem.chars();
+  let name = chars.next().map(|c| c.to_ascii_lowercase().to_string() + chars.as_str()).unwrap_or_default();
+
+  return if name.is_empty() || kKeywords.contains(&name.as_str()) { String::from("cf") } else { name };
+}
+
+fn wrapper(output: &mut String, name: &str, result: &Type, parameters: &[Parameter]) {
+  let handles = parameters.iter().filter(|parameter| is_handle(&parameter.ty) && !is_named(&parameter.ty, "CFAllocatorRef")).count();
+  let is_unsafe = parameters.iter().any(|parameter| matches!(parameter.ty, Type::Pointer { .. }) && !is_c_string(&parameter.ty));
+  let owned = name.contains("Create") || name.contains("Copy");
+  let borrowed = is_handle(result) && !owned;
+  let lifetime = if borrowed && handles > 0 { "'a" } else { "'static" };
+
+  let mut generics = Vec::new();
+  let mut declared = Vec::new();
+  let mut arguments = Vec::new();
+  let mut index = 0;
+
+  if borrowed && handles > 0 {
+    generics.push(String::from("'a"));
+  }
+
+  for parameter in parameters {
+    let name = identifier(&parameter.name);
+
+    match &parameter.ty {
+      Type::Named(handle) if handle == "CFAllocatorRef" => {
+        declared.push(format!("{}: Option<&CFAllocatorRef>", name));
+        arguments.push(format!("{}.map(raw)", name));
+      },
+      ty if is_handle(ty) => {
+        let generic = if handles == 1 { String::from("T") } else { format!("T{}", index + 1) };
+        let reference = if borrowed && index == 0 { "&'a " } else { "&" };
+
+        index += 1;
+        generics.push(format!("{}: Subtype<{}>", generic, rust_type(ty)));
+        declared.push(format!("{}: {}{}", name, reference, generic));
+        arguments.push(format!("raw({})", name));
+      },
+      ty if is_named(ty, "Boolean") => {
+        declared.push(format!("{}: bool", name));
+        arguments.push(format!("{}.into()", name));
+      },
+      ty if is_c_string(ty) => {
+        declared.push(format!("{}: &CStr", name));
+        arguments.push(format!("{}.as_ptr()", name));
+      },
+      ty => {
+        declared.push(format!("{}: {}", name, rust_type(ty)));
+        arguments.push(name);
+      }
+    }
+  }
+
+  let generics = if generics.is_empty() { String::new() } else { format!("<{}>", generics.join(", ")) };
+  let call = format!("ffi::{}({})", name, arguments.join(", "));
+  let (returns, call) = match result {
+    Type::Void => (String::new(), call),
+    ty if is_named(ty, "Boolean") => (String::from(" -> bool"), call),
+    Type::Named(handle) if owned && is_handle(result) => (format!(" -> Option<CFOwned<{}>>", handle), format!("{}.map(|{1}| CFOwned::from_create_rule({1}))", call, variable(handle))),
+    Type::Named(handle) if is_handle(result) => (format!(" -> Option<CFRef<{}, {}>>", lifetime, handle), format!("{}.map(|{1}| CFRef::from_get_rule({1}))", call, variable(handle))),
+    ty => (format!(" -> {}", rust_type(ty)), call)
+  };
+
+  let _ = writeln!(output, "pub {}fn {}{}({}){} {{", if is_unsafe { "unsafe " } else { "" }, name, generics, declared.join(", "), returns);
+
+  let _ = match (result, is_unsafe) {
+    (Type::Void, true) => writeln!(output, "  {};", call),
+    (Type::Void, false) => writeln!(output, "  unsafe {{ {} }};", call),
+    (ty, true) if is_named(ty, "Boolean") => writeln!(output, "  return {}.into();", call),
+    (ty, false) if is_named(ty, "Boolean") => writeln!(output, "  return unsafe {{ {} }}.into();", call),
+    (_, true) => writeln!(output, "  return {};", call),
+    (_, false) => writeln!(output, "  return unsafe {{ {} }};", call)
+  };
+
+  output.push_str("}\n");
+}
+
+fn externs(declarations: &[Declaration]) -> String {
+  let mut output = String::from("cf_extern! {\n");
+
+  for declaration in declarations {
+    let _ = match declaration {
+      Declaration::Function { name, result, parameters } => {
+        let parameters: Vec<String> = parameters.iter().map(|parameter| format!("{}: {}", identifier(&parameter.name), ext_parameter(&parameter.ty))).collect();
+
+        writeln!(output, "  pub fn {}({}){};", name, parameters.join(", "), ext_result(result))
+      },
+      Declaration::Constant { name, ty } => writeln!(output, "  pub static {}: {};", name, rust_type(ty)),
+      Declaration::Unsupported(declaration) => writeln!(output, "  // {}", declaration)
+    };
+  }
+
+  output.push_str("}\n");
+
+  return output;
+}
+
+// Writes only the `cf_extern!` block for `header`, in the order the header declares its functions and constants.
+pub fn generate_extern(header: &str) -> String {
+  return externs(&parse(header));
+}
+
+// Writes the bindings for `header` as the module `module`, which on other targets uses `crate::portable::<module>`.
+pub fn generate(header: &str, module: &str) -> String {
+  let declarations = parse(header);
+  let mut output = String::new();
+
+  output.push_str("use crate::*;\n\n#[cfg(target_vendor = \"apple\")]\npub(crate) mod ext {\n  use crate::*;\n\n");
+
+  for line in externs(&declarations).lines() {
+    let _ = writeln!(output, "  {}", line);
+  }
+
+  let _ = write!(output, "}}\n\n#[cfg(not(target_vendor = \"apple\"))]\npub(crate) use crate::portable::{0} as ext;\n\n#[cfg(not(feature = \"backend\"))]\nuse self::ext as ffi;\n\n#[cfg(feature = \"backend\")]\nuse crate::backend::{0} as ffi;\n", module);
+
+  for declaration in &declarations {
+    match declaration {
+      Declaration::Constant { name, ty } if is_handle(ty) => {
+        let _ = write!(output, "\npub static {0}: CFConstant<{1}> = cf_constant!(ext::{0});\n", name, rust_type(ty));
+      },
+      Declaration::Function { name, result, parameters } if !name.starts_with('_') => {
+        output.push('\n');
+        wrapper(&mut output, name, result, parameters);
+      },
+      _ => { }
+    }
+  }
+
+  return output;
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::*;
+
+  #[test]
+  fn it_parses_declarations() {
+    let declarations = parse("CF_EXPORT const UniChar *CFStringGetCharactersPtr(CFStringRef theString) CF_RETURNS_NOT_RETAINED;\nCF_EXPORT const CFStringRef kCFStringTransformToLatin;");
+    let unichar = Type::Named(String::from("UniChar"));
+    let string = Type::Named(String::from("CFStringRef"));
+
+    assert_eq!(vec![
+      Declaration::Function { name: String::from("CFStringGetCharactersPtr"), result: Type::Pointer { constant: true, pointee: Box::new(unichar) }, parameters: vec![Parameter { name: String::from("theString"), ty: string.clone() }] },
+      Declaration::Constant { name: String::from("kCFStringTransformToLatin"), ty: string }
+    ], declarations);
+  }
+
+  #[test]
+  fn it_keeps_unsupported_declarations() {
+    assert_eq!(vec![
+      Declaration::Unsupported(String::from("void CFStringAppendFormat(CFMutableStringRef theString, CFDictionaryRef formatOptions, CFStringRef format, ...);")),
+      Declaration::Unsupported(String::from("void CFRunLoopPerformBlock(CFRunLoopRef rl, CFTypeRef mode, void (^block)(void));"))
+    ], parse("void CFStringAppendFormat(CFMutableStringRef theString, CFDictionaryRef formatOptions, CFStringRef format, ...) CF_FORMAT_FUNCTION(2,3);\nvoid CFRunLoopPerformBlock(CFRunLoopRef rl, CFTypeRef mode, void (^block)(void));"));
+  }
+
+  #[test]
+  fn it_generates_fixtures() {
+    assert_eq!(include_str!("../fixtures/string.rs"), generate(include_str!("../fixtures/CFString.h"), "string"));
+    assert_eq!(include_str!("../fixtures/string_extern.rs"), generate_extern(include_str!("../fixtures/CFString.h")));
+  }
+
+  // The bindings written by hand before the generator existed are what it should reproduce.
+  #[test]
+  fn it_matches_existing_bindings() {
+    let existing = include_str!("../../core-foundation/src/string.rs");
+    let generated = generate(include_str!("../fixtures/CFString.h"), "string");
+
+    for name in ["CFStringGetTypeID", "CFStringCreateWithCString", "CFStringCreateWithBytes", "CFStringGetLength", "CFStringGetCString", "CFStringGetMaximumSizeForEncoding"] {
+      let declaration = generated.lines().find(|line| line.starts_with(&format!("    pub fn {}(", name))).unwrap();
+
+      assert!(existing.contains(declaration), "{}", declaration);
+    }
+
+    for name in ["CFStringGetTypeID", "CFStringCreateWithCString", "CFStringGetLength"] {
+      let start = generated.find(&format!("\npub fn {}", name)).unwrap();
+      let end = generated[start..].find("\n}\n").unwrap();
+
+      assert!(existing.contains(&generated[start..start + end + 3]), "{}", name);
+    }
+  }
+}
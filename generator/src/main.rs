@@ -0,0 +1,19 @@
+use std::{env, fs, process};
+
+// Prints the bindings for a CoreFoundation header, as in `hagane-generator CFString.h string`.
+fn main() {
+  let arguments: Vec<String> = env::args().collect();
+
+  let [_, header, module] = arguments.as_slice() else {
+    eprintln!("usage: hagane-generator <header> <module>");
+    process::exit(2);
+  };
+
+  match fs::read_to_string(header) {
+    Ok(header) => print!("{}", hagane_generator::generate(&header, module)),
+    Err(error) => {
+      eprintln!("{}: {}", header, error);
+      process::exit(1);
+    }
+  }
+}